@@ -0,0 +1,79 @@
+// Partitioning of the transaction graph by output amount.
+//
+// Before RingCT, a ring could only contain outputs with the same amount as
+// the output being spent. The transaction graph is then a disjoint union
+// of one subgraph per denomination, which can be analyzed separately.
+// RingCT outputs have the amount 0 and form a single subgraph.
+
+use std::collections::{BTreeMap, HashMap};
+
+// The amount of RingCT outputs and of the key images spending them
+pub const RINGCT_AMOUNT: u64 = 0;
+
+// The edges of one denomination with locally numbered vertices
+pub struct AmountSubgraph {
+    // Global key image index of each local key image
+    pub kis: Vec<u32>,
+    // Global public key index of each local public key
+    pub pks: Vec<u32>,
+    // Local public key index of each edge
+    pub pk_indices: Vec<u32>,
+    // Local key image index of each edge
+    pub ki_indices: Vec<u32>,
+}
+
+impl AmountSubgraph {
+    fn new() -> AmountSubgraph {
+        AmountSubgraph {
+            kis: vec![],
+            pks: vec![],
+            pk_indices: vec![],
+            ki_indices: vec![],
+        }
+    }
+}
+
+// Splits the edges into one subgraph per key image amount. Key images with
+// an unknown amount (u64::MAX) are skipped. If output amounts are given,
+// edges whose public key has a different amount than the key image are
+// skipped as well and their number is returned.
+pub fn partition_edges_by_amount(
+    pk_indices: &[u32],
+    ki_indices: &[u32],
+    ki_amounts: &[u64],
+    pk_amounts: Option<&[u64]>,
+) -> (BTreeMap<u64, AmountSubgraph>, usize) {
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let mut subgraphs: BTreeMap<u64, AmountSubgraph> = BTreeMap::new();
+    let mut local_kis: HashMap<u32, u32> = HashMap::new();
+    let mut local_pks: HashMap<(u64, u32), u32> = HashMap::new();
+    let mut num_mismatched = 0_usize;
+
+    for (pk, ki) in pk_indices.iter().zip(ki_indices.iter()) {
+        let amount = *ki_amounts.get(*ki as usize).unwrap_or(&u64::MAX);
+        if amount == u64::MAX {
+            continue;
+        }
+        if let Some(pk_amounts) = pk_amounts {
+            let pk_amount = *pk_amounts.get(*pk as usize).unwrap_or(&u64::MAX);
+            if pk_amount != u64::MAX && pk_amount != amount {
+                num_mismatched += 1;
+                continue;
+            }
+        }
+
+        let subgraph = subgraphs.entry(amount).or_insert_with(AmountSubgraph::new);
+        let local_ki = *local_kis.entry(*ki).or_insert_with(|| {
+            subgraph.kis.push(*ki);
+            (subgraph.kis.len() - 1) as u32
+        });
+        // A public key can appear in several subgraphs only if its amount is unknown
+        let local_pk = *local_pks.entry((amount, *pk)).or_insert_with(|| {
+            subgraph.pks.push(*pk);
+            (subgraph.pks.len() - 1) as u32
+        });
+        subgraph.ki_indices.push(local_ki);
+        subgraph.pk_indices.push(local_pk);
+    }
+    (subgraphs, num_mismatched)
+}
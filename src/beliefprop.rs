@@ -0,0 +1,157 @@
+// Approximate spend probabilities using loopy belief propagation.
+//
+// Every key image spends exactly one public key in its ring and every
+// public key is spent at most once. Belief propagation on this factor
+// graph approximates the fraction of valid spend assignments in which a key
+// image spends a given ring member. Its fixed points correspond to the
+// Bethe approximation of the permanent, so the results are close to the
+// exact probabilities of the matchings module on sparse graphs while
+// scaling linearly with the number of edges in the whole transaction graph.
+//
+// The messages are kept as ratios. For a key image k and public key p in
+// its ring,
+//   r(k -> p) = 1 / sum of rho(q -> k) over the other ring members q
+//   rho(p -> k) = 1 / (1 + sum of r(l -> p) over the other key images l
+//                          having p in their ring)
+// The 1 in the second message is the weight of p being unspent. The belief
+// that k spends p is proportional to rho(p -> k).
+
+// Default damping factor applied to the public key messages
+pub const DEFAULT_DAMPING: f64 = 0.5;
+// Default maximum change in a message at convergence
+pub const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+pub struct BeliefPropagation {
+    // Edges are numbered in ring order. The edges of key image k are
+    // ki_ptr[k]..ki_ptr[k+1].
+    ki_ptr: Vec<usize>,
+    // Start of the edges of each public key in pk_edges
+    pk_ptr: Vec<usize>,
+    // Edge indices grouped by public key
+    pk_edges: Vec<usize>,
+    // Message from the public key to the key image of each edge
+    rho: Vec<f64>,
+    // Message from the key image to the public key of each edge
+    r: Vec<f64>,
+    pub num_iterations: usize,
+    // Maximum change in a public key message in the last iteration
+    pub max_change: f64,
+}
+
+impl BeliefPropagation {
+    pub fn new(
+        tx_rings: &[Vec<u32>],
+        num_pks: usize,
+    ) -> BeliefPropagation {
+        let mut ki_ptr: Vec<usize> = Vec::with_capacity(tx_rings.len() + 1);
+        let mut pk_of_edge: Vec<u32> = vec![];
+        ki_ptr.push(0);
+        for ring in tx_rings {
+            pk_of_edge.extend_from_slice(ring);
+            ki_ptr.push(pk_of_edge.len());
+        }
+
+        let mut pk_ptr: Vec<usize> = vec![0; num_pks + 1];
+        for pk in &pk_of_edge {
+            pk_ptr[*pk as usize + 1] += 1;
+        }
+        for p in 0..num_pks {
+            pk_ptr[p + 1] += pk_ptr[p];
+        }
+        let mut next = pk_ptr.clone();
+        let mut pk_edges: Vec<usize> = vec![0; pk_of_edge.len()];
+        for (e, pk) in pk_of_edge.iter().enumerate() {
+            pk_edges[next[*pk as usize]] = e;
+            next[*pk as usize] += 1;
+        }
+
+        let num_edges = pk_of_edge.len();
+        BeliefPropagation {
+            ki_ptr,
+            pk_ptr,
+            pk_edges,
+            rho: vec![1.0; num_edges],
+            r: vec![0.0; num_edges],
+            num_iterations: 0,
+            max_change: f64::INFINITY,
+        }
+    }
+
+    // Performs one round of message updates and returns the maximum change
+    // in a public key message
+    pub fn iterate(
+        &mut self,
+        damping: f64,
+    ) -> f64 {
+        // Key image to public key messages
+        for k in 0..self.ki_ptr.len() - 1 {
+            let edges = self.ki_ptr[k]..self.ki_ptr[k+1];
+            let total: f64 = self.rho[edges.clone()].iter().sum();
+            for e in edges {
+                let others = total - self.rho[e];
+                // A public key that is the only possible spend of the key image
+                // gets an infinite message
+                self.r[e] = if others > 0.0 { 1.0 / others } else { f64::INFINITY };
+            }
+        }
+
+        // Public key to key image messages. Infinite messages are counted
+        // separately so that a public key is blocked for the other key images
+        // exactly when one of them must spend it.
+        let mut max_change = 0.0_f64;
+        for p in 0..self.pk_ptr.len() - 1 {
+            let edges = &self.pk_edges[self.pk_ptr[p]..self.pk_ptr[p+1]];
+            let mut finite_sum = 1.0;
+            let mut num_infinite = 0_usize;
+            for e in edges {
+                if self.r[*e].is_finite() {
+                    finite_sum += self.r[*e];
+                }
+                else {
+                    num_infinite += 1;
+                }
+            }
+            for e in edges {
+                let new_rho = if self.r[*e].is_finite() {
+                    if num_infinite > 0 { 0.0 } else { 1.0 / (finite_sum - self.r[*e]).max(1.0) }
+                }
+                else if num_infinite > 1 {
+                    0.0
+                }
+                else {
+                    1.0 / finite_sum
+                };
+                let damped = damping * self.rho[*e] + (1.0 - damping) * new_rho;
+                max_change = max_change.max((damped - self.rho[*e]).abs());
+                self.rho[*e] = damped;
+            }
+        }
+
+        self.num_iterations += 1;
+        self.max_change = max_change;
+        max_change
+    }
+
+    // Spend probabilities of the members of each ring in ring order. Rings
+    // whose messages are all zero are contradictory under the current
+    // messages and get uniform probabilities. Their number is also returned.
+    pub fn beliefs(&self) -> (Vec<Vec<f64>>, usize) {
+        let mut num_contradictory = 0_usize;
+        let probs = (0..self.ki_ptr.len() - 1)
+            .map(|k| {
+                let rho = &self.rho[self.ki_ptr[k]..self.ki_ptr[k+1]];
+                let total: f64 = rho.iter().sum();
+                if total > 0.0 {
+                    rho.iter().map(|x| x / total).collect()
+                }
+                else {
+                    if !rho.is_empty() {
+                        num_contradictory += 1;
+                    }
+                    vec![1.0 / rho.len() as f64; rho.len()]
+                }
+            })
+            .collect();
+        (probs, num_contradictory)
+    }
+}
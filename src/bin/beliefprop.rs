@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_rings, read_edge_probabilities};
+use xmrtrace::beliefprop::{BeliefPropagation, DEFAULT_DAMPING, DEFAULT_TOLERANCE};
+
+fn main() {
+    let cmd = Command::new("Belief Propagation Spend Probabilities")
+    .bin_name("beliefprop")
+    .arg(
+        Arg::new("rings_file")
+            .value_name("Rings Filename")
+            .required(true)
+            .long_help("The name of the file containing a list of rings, for example after the DM decomposition")
+    )
+    .arg(
+        Arg::new("probability_file")
+            .value_name("Probability Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the approximate spend probability of each edge")
+    )
+    .arg(
+        Arg::new("max_iterations")
+            .long("max-iterations")
+            .value_name("Maximum Iterations")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1000")
+            .long_help("The maximum number of rounds of message updates")
+    )
+    .arg(
+        Arg::new("damping")
+            .long("damping")
+            .value_name("Damping")
+            .value_parser(clap::value_parser!(f64))
+            .long_help("The weight of the previous message in each update, between 0 and 1. Defaults to 0.5")
+    )
+    .arg(
+        Arg::new("tolerance")
+            .long("tolerance")
+            .value_name("Tolerance")
+            .value_parser(clap::value_parser!(f64))
+            .long_help("The iterations stop when no message changes by more than this value. Defaults to 1e-6")
+    )
+    .arg(
+        Arg::new("report_every")
+            .long("report-every")
+            .value_name("Report Interval")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("10")
+            .long_help("Print the maximum message change every this many iterations. Zero disables the reports")
+    )
+    .arg(
+        Arg::new("compare_file")
+            .long("compare")
+            .value_name("Exact Probability Filename")
+            .long_help("The name of a probability file written by the marginals command. \
+            The approximate probabilities of its edges are compared with the probabilities in the file")
+    )
+    .after_help("The beliefprop command approximates the probability that each key image spends each member of \
+    its ring using loopy belief propagation over the whole transaction graph. \
+    Each row of the output file has a key image index, a public key index and the probability separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let rings_fname = m.get_one::<String>("rings_file").unwrap();
+    let prob_fname = m.get_one::<String>("probability_file").unwrap();
+    let max_iterations = *m.get_one::<usize>("max_iterations").unwrap();
+    let damping = *m.get_one::<f64>("damping").unwrap_or(&DEFAULT_DAMPING);
+    let tolerance = *m.get_one::<f64>("tolerance").unwrap_or(&DEFAULT_TOLERANCE);
+    let report_every = *m.get_one::<usize>("report_every").unwrap();
+    assert!((0.0..1.0).contains(&damping), "Damping must be at least 0 and less than 1");
+
+    let mut start_instant = Instant::now();
+    let (tx_rings, pk_to_ki_map) = read_rings(rings_fname);
+    let mut end_instant = Instant::now();
+    println!("Rings file read in {:?}", end_instant.duration_since(start_instant));
+    println!("Num keyimages = {}, Num public keys = {}", tx_rings.len(), pk_to_ki_map.len());
+
+    start_instant = Instant::now();
+    let mut bp = BeliefPropagation::new(&tx_rings, pk_to_ki_map.len());
+    let mut converged = false;
+    while bp.num_iterations < max_iterations {
+        let change = bp.iterate(damping);
+        if report_every > 0 && bp.num_iterations.is_multiple_of(report_every) {
+            println!("Iteration {}: maximum message change = {:e}", bp.num_iterations, change);
+        }
+        if change <= tolerance {
+            converged = true;
+            break;
+        }
+    }
+    let (probs, num_contradictory) = bp.beliefs();
+    end_instant = Instant::now();
+    println!("Belief propagation ran in {:?}", end_instant.duration_since(start_instant));
+
+    if converged {
+        println!("Converged after {} iterations", bp.num_iterations);
+    }
+    else {
+        println!("Did not converge after {} iterations", bp.num_iterations);
+    }
+    println!("Final maximum message change = {:e}", bp.max_change);
+    println!("Rings with contradictory messages = {}", num_contradictory);
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(prob_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+    for (ki, ring) in tx_rings.iter().enumerate() {
+        for (pk, p) in ring.iter().zip(&probs[ki]) {
+            writeln!(buf, "{} {} {}", ki, pk, p).expect(file_err_msg);
+        }
+    }
+
+    if let Some(compare_fname) = m.get_one::<String>("compare_file") {
+        let approx: HashMap<(u32, u32), f64> = tx_rings.iter().enumerate()
+            .flat_map(|(ki, ring)| {
+                ring.iter().zip(&probs[ki]).map(move |(pk, p)| ((ki as u32, *pk), *p))
+            })
+            .collect();
+
+        let mut num_compared = 0_usize;
+        let mut num_missing = 0_usize;
+        let mut sum_abs_err = 0.0;
+        let mut max_abs_err = 0.0_f64;
+        for (ki, pk, p) in read_edge_probabilities(compare_fname) {
+            match approx.get(&(ki, pk)) {
+                Some(q) => {
+                    let err = (p - q).abs();
+                    num_compared += 1;
+                    sum_abs_err += err;
+                    max_abs_err = max_abs_err.max(err);
+                },
+                None => num_missing += 1,
+            }
+        }
+        println!("Edges compared with {} = {}", compare_fname, num_compared);
+        println!("Edges missing from the rings file = {}", num_missing);
+        if num_compared > 0 {
+            println!("Mean absolute error = {}", sum_abs_err / num_compared as f64);
+            println!("Maximum absolute error = {}", max_abs_err);
+        }
+    }
+}
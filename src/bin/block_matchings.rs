@@ -0,0 +1,126 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_rings, read_fine_decomposition};
+use xmrtrace::matchings::{BlockGraph, MAX_PERMANENT_SIZE, enumerate_perfect_matchings, sample_perfect_matchings};
+
+fn main() {
+    let cmd = Command::new("Perfect Matchings of a DM Fine Decomposition Block")
+    .bin_name("block_matchings")
+    .arg(
+        Arg::new("post_dmd_rings_file")
+            .value_name("Post DM Decomposition Rings Filename")
+            .required(true)
+            .long_help("The name of the file containing a list of rings after the DM decomposition")
+    )
+    .arg(
+        Arg::new("fine_decomposition_file")
+            .value_name("Fine Decomposition Filename")
+            .required(true)
+            .long_help("The name of the file containing the closed sets in the DM fine decomposition")
+    )
+    .arg(
+        Arg::new("block_id")
+            .value_name("Block Index")
+            .value_parser(clap::value_parser!(usize))
+            .required(true)
+            .long_help("The index of the block in the fine decomposition file, starting from 0")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Matchings Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the perfect matchings of the block")
+    )
+    .arg(
+        Arg::new("limit")
+            .long("limit")
+            .value_name("Limit")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1000")
+            .long_help("The maximum number of perfect matchings to enumerate")
+    )
+    .arg(
+        Arg::new("num_samples")
+            .long("sample")
+            .value_name("Number of Samples")
+            .value_parser(clap::value_parser!(usize))
+            .long_help("Sample this many perfect matchings uniformly at random instead of enumerating them")
+    )
+    .arg(
+        Arg::new("exact_limit")
+            .long("exact-limit")
+            .value_name("Exact Block Size Limit")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("12")
+            .long_help("Blocks with at most this many key images are sampled exactly using permanents. The limit can be at most 22. \
+            Larger blocks are sampled using a Markov chain")
+    )
+    .arg(
+        Arg::new("num_steps")
+            .long("num-steps")
+            .value_name("Number of Steps")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("100000")
+            .long_help("The number of Markov chain steps between samples of a large block")
+    )
+    .arg(
+        Arg::new("seed")
+            .long("seed")
+            .value_name("Seed")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .long_help("The seed of the random number generator used for sampling")
+    )
+    .after_help("The block_matchings command enumerates or samples the perfect matchings of one block of the \
+    DM fine decomposition. The first line of the output file has the key image indices of the block. \
+    Each subsequent line has one perfect matching given as the public key index matched to each of these \
+    key images, in the same order.");
+
+
+    let m = cmd.get_matches();
+    let rings_fname = m.get_one::<String>("post_dmd_rings_file").unwrap();
+    let fine_decomp_fname = m.get_one::<String>("fine_decomposition_file").unwrap();
+    let block_id = *m.get_one::<usize>("block_id").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+    let limit = *m.get_one::<usize>("limit").unwrap();
+    let exact_limit = *m.get_one::<usize>("exact_limit").unwrap();
+    assert!(exact_limit <= MAX_PERMANENT_SIZE, "Exact block size limit must be at most {}", MAX_PERMANENT_SIZE);
+    let num_steps = *m.get_one::<usize>("num_steps").unwrap();
+    let seed = *m.get_one::<u64>("seed").unwrap();
+
+    let mut start_instant = Instant::now();
+    let (tx_rings, _pk_to_ki_map) = read_rings(rings_fname);
+    let blocks = read_fine_decomposition(fine_decomp_fname);
+    let mut end_instant = Instant::now();
+    println!("Rings and fine decomposition files read in {:?}", end_instant.duration_since(start_instant));
+
+    assert!(block_id < blocks.len(), "Block index must be less than the number of blocks {}", blocks.len());
+    let (pks, kis) = &blocks[block_id];
+    let block = BlockGraph::new(pks, kis, &tx_rings);
+    println!("Block {} has {} keyimages", block_id, block.size());
+
+    start_instant = Instant::now();
+    let matchings = match m.get_one::<usize>("num_samples") {
+        Some(num_samples) => sample_perfect_matchings(&block, *num_samples, exact_limit, num_steps, seed),
+        None => enumerate_perfect_matchings(&block, limit),
+    };
+    end_instant = Instant::now();
+    println!("Found {} perfect matchings in {:?}", matchings.len(), end_instant.duration_since(start_instant));
+    if m.get_one::<usize>("num_samples").is_none() && matchings.len() == limit {
+        println!("Enumeration stopped at the limit of {} matchings", limit);
+    }
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    let ki_strs: Vec<String> = block.kis.iter().map(|ki| ki.to_string()).collect();
+    writeln!(buf, "{}", ki_strs.join(" ")).expect(file_err_msg);
+    for matching in &matchings {
+        let pk_strs: Vec<String> = matching.iter().map(|j| block.pks[*j].to_string()).collect();
+        writeln!(buf, "{}", pk_strs.join(" ")).expect(file_err_msg);
+    }
+}
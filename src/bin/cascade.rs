@@ -1,7 +1,10 @@
 use std::time::Instant;
 use clap::{Arg, Command};
 
-use xmrtrace::{read_edges, write_rings};
+use xmrtrace::{write_rings, cascade_attack, cascade_attack_iteration};
+use xmrtrace::interner::{dictionary_args, read_edges_with_args};
+use xmrtrace::sideinfo::{apply_known_spends_from_args, known_spends_arg};
+use xmrtrace::temporal::{edge_height_args, preprocess_edges_by_height};
 
 fn main() {
     let cmd = Command::new("CryptoNote Cascade Attack")
@@ -26,6 +29,9 @@ fn main() {
             .default_value("1")
             .long_help("The number of Cascade Attack iterations")
     )
+    .args(edge_height_args())
+    .arg(known_spends_arg())
+    .args(dictionary_args())
     .after_help("The cascade command executes the Cascade Attack aka zero-mixin chain reaction attack on a set of CryptoNote transactions. \
     The transaction graph is described in a file. Each row in the file describes an edge. The first two entries in each row are non-negative integers separated by a space. The first integer is a key image identifier and the second integer is the public key identifier. The identifier spaces can overlap.");
 
@@ -36,31 +42,29 @@ fn main() {
     let num_iterations = m.get_one::<u16>("num_iterations").unwrap();
 
     let start_instant = Instant::now();
-    let (pk_indices, ki_indices, max_pk_index, max_ki_index) = read_edges(input_fname);
+    let (mut pk_indices, mut ki_indices, max_pk_index, max_ki_index) = read_edges_with_args(input_fname, &m);
     let end_instant = Instant::now();
     println!("Edge file read in {:?}", end_instant.duration_since(start_instant));
 
+    print!("{}", preprocess_edges_by_height(&m, &mut pk_indices, &mut ki_indices));
+
 
     let num_pks = (max_pk_index+1) as usize;
     let num_kis = (max_ki_index+1) as usize ;
     println!("Num keyimages = {}, Num public keys = {}", num_kis, num_pks);
 
-    // Each index in tx_rings corresponds to one key image.
-    // The vector at that index has the ring of public key indices.
-    let mut tx_rings: Vec<Vec<u32>> = vec![vec![]; num_kis];
-    // Each index in pk_to_ki_map corresponds to one public key.
-    // The vector at that index has the indices of the corresponding key images.
-    let mut pk_to_ki_map: Vec<Vec<u32>> = vec![vec![]; num_pks];
-
-    assert_eq!(pk_indices.len(), ki_indices.len());
-    let num_edges = ki_indices.len();
-
-    for i in 0..num_edges {
-       tx_rings[ki_indices[i] as usize].push(pk_indices[i]); 
-       pk_to_ki_map[pk_indices[i] as usize].push(ki_indices[i]);
+    let side_info = apply_known_spends_from_args(&m, &mut pk_indices, &mut ki_indices, |pk_indices, ki_indices| {
+        let (mut base_tx_rings, base_pk_to_ki_map) = build_rings(pk_indices, ki_indices, num_pks, num_kis);
+        let num_traced = cascade_attack(&mut base_tx_rings, &base_pk_to_ki_map, *num_iterations as usize);
+        println!("Zero-mixin rings after CA without side information = {}", num_traced);
+        base_tx_rings.iter().map(|ring| ring.len() == 1).collect()
+    });
+    if let Some(side_info) = &side_info {
+        print!("{}", side_info.summary);
     }
-    // println!("{:?}", tx_rings);
-    // println!("{:?}", pk_to_ki_map);
+
+    let (mut tx_rings, pk_to_ki_map) = build_rings(&pk_indices, &ki_indices, num_pks, num_kis);
+    let num_empty_rings = tx_rings.iter().filter(|ring| ring.is_empty()).count();
 
     let mut tx_ring_traced: Vec<bool> = vec![false; num_kis];
     let mut num_traceable_rings = 0_usize;
@@ -75,32 +79,7 @@ fn main() {
 
     for iter_index in 0..*num_iterations {
         let start_instant = Instant::now();
-        for i in 0..num_kis {
-            if tx_ring_traced[i] == false && tx_rings[i].len() == 1 {
-                let traced_pk = tx_rings[i][0];
-
-                for ki in pk_to_ki_map[traced_pk as usize].clone().into_iter() {
-                    if ki != (i as u32) {
-                        let ring = tx_rings[ki as usize].clone();
-                        for j in 0..ring.len() {
-                            if ring[j] == traced_pk {
-                                tx_rings[ki as usize].swap_remove(j);
-                                break; // Assumes that ring keys are unique
-                            }
-                        }
-                    }
-                }
-
-                tx_ring_traced[i] = true;
-            }
-        }
-        num_traceable_rings = 0;
-        for i in 0..num_kis {
-            if tx_rings[i].len() == 1 {
-                num_traceable_rings += 1;
-            }
-        }
-
+        num_traceable_rings = cascade_attack_iteration(&mut tx_rings, &pk_to_ki_map, &mut tx_ring_traced);
         let end_instant = Instant::now();
 
         println!("Zero-mixin rings after CA iteration {} = {}. Time taken = {:?}.",
@@ -115,5 +94,37 @@ fn main() {
         }
         prev_num_traceable_rings = num_traceable_rings;
     }
+
+    if let Some(side_info) = &side_info {
+        println!("Rings emptied by contradictory known spends = {}",
+            tx_rings.iter().filter(|ring| ring.is_empty()).count() - num_empty_rings
+        );
+        let traced_with: Vec<bool> = tx_rings.iter().map(|ring| ring.len() == 1).collect();
+        println!("Rings traced because of the known spends (knock-on) = {}", side_info.knock_on_traced(&traced_with));
+    }
     write_rings(tx_rings, num_pks, output_fname);
-}
\ No newline at end of file
+}
+
+// Builds the rings and the map from public keys to key images from a list of edges
+fn build_rings(
+    pk_indices: &[u32],
+    ki_indices: &[u32],
+    num_pks: usize,
+    num_kis: usize,
+) -> (Vec<Vec<u32>>, Vec<Vec<u32>>) {
+    // Each index in tx_rings corresponds to one key image.
+    // The vector at that index has the ring of public key indices.
+    let mut tx_rings: Vec<Vec<u32>> = vec![vec![]; num_kis];
+    // Each index in pk_to_ki_map corresponds to one public key.
+    // The vector at that index has the indices of the corresponding key images.
+    let mut pk_to_ki_map: Vec<Vec<u32>> = vec![vec![]; num_pks];
+
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let num_edges = ki_indices.len();
+
+    for i in 0..num_edges {
+       tx_rings[ki_indices[i] as usize].push(pk_indices[i]); 
+       pk_to_ki_map[pk_indices[i] as usize].push(ki_indices[i]);
+    }
+    (tx_rings, pk_to_ki_map)
+}
@@ -5,7 +5,9 @@ use petgraph::prelude::DiGraphMap;
 use petgraph::algo::tarjan_scc;
 use petgraph::algo::maximum_matching;
 
-use xmrtrace::{read_rings, write_ring_sets, remove_closed_set_pks};
+use xmrtrace::{read_rings, read_known_spends, write_ring_sets, remove_closed_set_pks, cascade_attack};
+use xmrtrace::sideinfo::{apply_known_spends_to_rings, count_knock_on_traced, known_spends_arg};
+use xmrtrace::chains::{histogram_chain_arg, histogram_ring_size};
 
 fn main() {
     let cmd = Command::new("Clustering Algorithm for Closed Set Attack")
@@ -22,6 +24,8 @@ fn main() {
             .required(true)
             .long_help("The name of the file containing a list of rings after the clustering algorithm")
     )
+    .arg(known_spends_arg())
+    .arg(histogram_chain_arg())
     .after_help("The cluster command executes the clustering algorithm to implement the closed set attack of Yu et al (FC 2019).\
     The input file has a list of rings that have already been subjected to the cascade attack. The first line of the file has the \
     number of rings. Each subsequent line begins with the index of the key image, followed by the indices of the public keys in the \
@@ -46,16 +50,97 @@ fn main() {
     end_instant = Instant::now();
     println!("Ring sets created in {:?}", end_instant.duration_since(start_instant));
 
+    let known_spends = m.get_one::<String>("known_spends_file").map(|f| read_known_spends(f));
+    match &known_spends {
+        None => clustering_algorithm(&mut tx_ring_sets, &pk_to_ki_map),
+        Some(known_spends) => {
+            println!("Running clustering algorithm without side information");
+            let mut base_tx_ring_sets = tx_ring_sets.clone();
+            clustering_algorithm(&mut base_tx_ring_sets, &pk_to_ki_map);
+            let traced_without: Vec<bool> = base_tx_ring_sets.iter().map(|ring| ring.len() == 1).collect();
+
+            // The known spends are applied to the input rings, which are then
+            // subjected to the cascade attack again before clustering
+            let mut side_info_rings = tx_rings.clone();
+            let summary = apply_known_spends_to_rings(&mut side_info_rings, known_spends);
+            print!("{}", summary);
+            let num_traced = cascade_attack(&mut side_info_rings, &pk_to_ki_map, usize::MAX);
+            println!("Zero-mixin rings after cascade attack with side information = {}", num_traced);
+            let num_emptied = (0..tx_rings.len())
+                .filter(|ki| side_info_rings[*ki].is_empty() && !tx_rings[*ki].is_empty())
+                .count();
+            println!("Rings emptied by contradictory known spends = {}", num_emptied);
+
+            let mut side_info_pk_to_ki_map: Vec<Vec<u32>> = vec![vec![]; pk_to_ki_map.len()];
+            for (ki, ring) in side_info_rings.iter().enumerate() {
+                for pk in ring {
+                    side_info_pk_to_ki_map[*pk as usize].push(ki as u32);
+                }
+            }
+            tx_ring_sets = side_info_rings.iter()
+                .map(|ring| HashSet::from_iter(ring.iter().copied()))
+                .collect();
+
+            println!("Running clustering algorithm with side information");
+            clustering_algorithm(&mut tx_ring_sets, &side_info_pk_to_ki_map);
+            let traced_with: Vec<bool> = tx_ring_sets.iter().map(|ring| ring.len() == 1).collect();
+            println!("Rings traced because of the known spends (knock-on) = {}",
+                count_knock_on_traced(&traced_without, &traced_with, known_spends)
+            );
+        },
+    }
+
+    let max_ring_size = histogram_ring_size(&m);
+    let mut pre_attack_mixin_histogram = vec![0_u32; max_ring_size+1];
+    let mut post_attack_mixin_histogram = vec![0_u32; max_ring_size+1];
+    for ki in 0..tx_ring_sets.len() {
+        let mut l = tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
+        
+        if l <= max_ring_size {
+            pre_attack_mixin_histogram[l-1] += 1;
+        }
+        else {
+            pre_attack_mixin_histogram[max_ring_size] += 1;
+        }
+
+        l = tx_ring_sets[ki].len();
+        if l == 0 {
+            continue; // Ring emptied by contradictory known spends
+        }
+
+        if l <= max_ring_size {
+            post_attack_mixin_histogram[l-1] += 1;
+        }
+        else {
+            post_attack_mixin_histogram[max_ring_size] += 1;
+        }
+    }
+
+    println!("Pre attack mixin histogram:\n {:?}", pre_attack_mixin_histogram);
+    println!("Post attack mixin histogram:\n {:?}", post_attack_mixin_histogram);
+
+    write_ring_sets(&tx_ring_sets, pk_to_ki_map.len(), output_fname);
+}
+
+// Clustering algorithm (pp 11 of Yu et al, FC 2019). The public keys of the
+// closed sets found are removed from the rings of the other key images.
+fn clustering_algorithm(
+    tx_ring_sets: &mut Vec<HashSet<u32>>,
+    pk_to_ki_map: &Vec<Vec<u32>>,
+) {
     let mut num_traceable_rings = 0_u32;
     let mut ki_in_closed_set = vec![false; tx_ring_sets.len()];
-    start_instant = Instant::now();
+    let start_instant = Instant::now();
     for ki in 0..tx_ring_sets.len() {
         if tx_ring_sets[ki].len() == 1 {
             ki_in_closed_set[ki] = true;
             num_traceable_rings += 1;
         }
     }
-    end_instant = Instant::now();
+    let end_instant = Instant::now();
     println!("Counted initial number of traceable rings in {:?}", end_instant.duration_since(start_instant));
     println!("Number of traceable rings = {}", num_traceable_rings);
 
@@ -65,7 +150,6 @@ fn main() {
     let mut set_of_closed_sets = HashSet::<Vec<u32>>::new();
     let mut set_of_all_closed_set_pks = HashSet::<u32>::new();
 
-    // Clustering algorithm (pp 11 of Yu et al, FC 2019)
     while flag {
         println!("At beginning of clustering algorithm while loop");
         search_iteration += 1;
@@ -73,7 +157,7 @@ fn main() {
         let mut num_clusters_found = 0_u32;
         for ki in 0..tx_ring_sets.len() {
             if tx_ring_sets[ki].len() != 1 && ki_in_closed_set[ki] == false {
-                let (clus_ki_set, clus_pk_set) = form_cluster(ki, &tx_ring_sets, &pk_to_ki_map, &ki_in_closed_set);
+                let (clus_ki_set, clus_pk_set) = form_cluster(ki, tx_ring_sets, pk_to_ki_map, &ki_in_closed_set);
 
                 if clus_ki_set.len() == clus_pk_set.len() {
                     num_clusters_found += 1;
@@ -83,7 +167,7 @@ fn main() {
                         ki,
                         search_iteration,
                     );
-                    let closed_set_ki_pk_set_pairs = decompose_closed_set(&clus_ki_set, &clus_pk_set, &tx_ring_sets);
+                    let closed_set_ki_pk_set_pairs = decompose_closed_set(&clus_ki_set, &clus_pk_set, tx_ring_sets);
                     for key_im in &clus_ki_set {
                         ki_in_closed_set[*key_im as usize] = true;
                     }
@@ -94,7 +178,7 @@ fn main() {
                         set_of_closed_sets.insert(closet_ki_set_vec);
                         set_of_all_closed_set_pks.extend(closed_pk_set.clone());
 
-                        if remove_closed_set_pks(closed_ki_set, closed_pk_set, &mut tx_ring_sets, &pk_to_ki_map) {
+                        if remove_closed_set_pks(closed_ki_set, closed_pk_set, tx_ring_sets, pk_to_ki_map) {
                             flag = true;  // If a public key is removed, we should run the cluster search again
                         };
                     }
@@ -128,34 +212,6 @@ fn main() {
         println!("Number of public keys in all sets = {}", set_of_all_closed_set_pks.len());
         println!("Number of public keys in non-singleton closed sets = {}", set_of_all_closed_set_pks.len() - (num_singleton_closed_sets as usize));
     }
-
-    let max_ring_size = 10_usize;
-    let mut pre_attack_mixin_histogram = vec![0_u32; max_ring_size+1];
-    let mut post_attack_mixin_histogram = vec![0_u32; max_ring_size+1];
-    for ki in 0..tx_ring_sets.len() {
-        let mut l = tx_rings[ki].len();
-        
-        if l <= max_ring_size {
-            pre_attack_mixin_histogram[l-1] += 1;
-        }
-        else {
-            pre_attack_mixin_histogram[max_ring_size] += 1;
-        }
-
-        l = tx_ring_sets[ki].len();
-
-        if l <= max_ring_size {
-            post_attack_mixin_histogram[l-1] += 1;
-        }
-        else {
-            post_attack_mixin_histogram[max_ring_size] += 1;
-        }
-    }
-
-    println!("Pre attack mixin histogram:\n {:?}", pre_attack_mixin_histogram);
-    println!("Post attack mixin histogram:\n {:?}", post_attack_mixin_histogram);
-
-    write_ring_sets(&tx_ring_sets, pk_to_ki_map.len(), output_fname);
 }
 
 fn form_cluster(
@@ -0,0 +1,95 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_edges, read_amounts};
+use xmrtrace::amounts::{partition_edges_by_amount, RINGCT_AMOUNT};
+use xmrtrace::dm::{build_txgraph, dm_decomposition};
+
+fn main() {
+    let cmd = Command::new("Amount-Partitioned DM Decomposition")
+    .bin_name("denominations")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Input Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges")
+    )
+    .arg(
+        Arg::new("ki_amounts_file")
+            .value_name("Key Image Amounts Filename")
+            .required(true)
+            .long_help("The name of the file containing the amount of the output spent by each key image")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Denomination Statistics Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the statistics of each denomination")
+    )
+    .arg(
+        Arg::new("pk_amounts_file")
+            .long("pk-amounts")
+            .value_name("Public Key Amounts Filename")
+            .long_help("The name of the file containing the amount of each output. Ring members whose amount \
+            differs from the amount of the key image are removed and counted")
+    )
+    .after_help("The denominations command calculates the DM decomposition of the subgraph of each denomination \
+    separately. Each row of an amounts file has an index followed by an amount in atomic units, separated by a space. \
+    RingCT outputs have the amount 0. Each row of the output file has the amount, the number of key images, the \
+    number of public keys, the number of traced key images and the traced fraction, separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let input_fname = m.get_one::<String>("input_file").unwrap();
+    let ki_amounts_fname = m.get_one::<String>("ki_amounts_file").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+
+    let mut start_instant = Instant::now();
+    let (pk_indices, ki_indices, _max_pk_index, _max_ki_index) = read_edges(input_fname);
+    let ki_amounts = read_amounts(ki_amounts_fname);
+    let pk_amounts = m.get_one::<String>("pk_amounts_file").map(|f| read_amounts(f));
+    let mut end_instant = Instant::now();
+    println!("Edge and amount files read in {:?}", end_instant.duration_since(start_instant));
+
+    start_instant = Instant::now();
+    let (subgraphs, num_mismatched) = partition_edges_by_amount(&pk_indices, &ki_indices, &ki_amounts, pk_amounts.as_deref());
+    end_instant = Instant::now();
+    println!("Edges partitioned in {:?}", end_instant.duration_since(start_instant));
+    println!("Number of denominations = {}", subgraphs.len());
+    println!("Ring members with a different amount (removed) = {}", num_mismatched);
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    // Number of key images and traced key images for RingCT and non-RingCT
+    let mut ringct_totals = (0_usize, 0_usize);
+    let mut non_ringct_totals = (0_usize, 0_usize);
+
+    start_instant = Instant::now();
+    for (amount, subgraph) in subgraphs {
+        let num_kis = subgraph.kis.len();
+        let num_pks = subgraph.pks.len();
+        let g = build_txgraph(subgraph.pk_indices, subgraph.ki_indices, num_pks, num_kis);
+        let num_traced = dm_decomposition(&g).num_singletons();
+
+        writeln!(buf, "{} {} {} {} {}", amount, num_kis, num_pks, num_traced, num_traced as f64 / num_kis as f64)
+            .expect(file_err_msg);
+        let totals = if amount == RINGCT_AMOUNT { &mut ringct_totals } else { &mut non_ringct_totals };
+        totals.0 += num_kis;
+        totals.1 += num_traced;
+    }
+    end_instant = Instant::now();
+    println!("DM decompositions calculated in {:?}", end_instant.duration_since(start_instant));
+
+    for (era, (num_kis, num_traced)) in [("RingCT", ringct_totals), ("Non-RingCT", non_ringct_totals)] {
+        if num_kis > 0 {
+            println!("{} keyimages = {}, traced = {} ({:.4})", era, num_kis, num_traced, num_traced as f64 / num_kis as f64);
+        }
+        else {
+            println!("{} keyimages = 0", era);
+        }
+    }
+}
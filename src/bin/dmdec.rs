@@ -3,10 +3,12 @@ use std::{fs::File, io::{BufWriter, Write}};
 use std::time::Instant;
 use std::collections::{HashSet, BTreeMap};
 use clap::{Arg, Command};
-use petgraph::{graphmap::DiGraphMap, algo::tarjan_scc};
-use sprs::{TriMatBase, CsMatBase};
 
-use xmrtrace::{read_edges, cn_txgraph_maximum_matching, write_ring_sets, remove_closed_set_pks};
+use xmrtrace::{write_ring_sets, remove_closed_set_pks};
+use xmrtrace::dm::{block_triangular_form, build_txgraph, fine_block_dag, dm_decomposition, dm_decomposition_incremental, read_dm_state, write_block_triangular_form, verify_maximum_matching, write_dm_state, write_fine_block_dag, DmDecomposition, DmState, SpentStatus};
+use xmrtrace::interner::{dictionary_args, read_edges_with_args};
+use xmrtrace::sideinfo::{apply_known_spends_from_args, known_spends_arg};
+use xmrtrace::temporal::{edge_height_args, preprocess_edges_by_height};
 
 fn main() {
     let cmd = Command::new("Dulmage-Mendelsohn Decomposition Calculator")
@@ -41,6 +43,51 @@ fn main() {
             .required(true)
             .long_help("The name of the output file that will have a list of closed sets in the DM fine decomposition")
     )
+    .args(edge_height_args())
+    .arg(
+        Arg::new("state_file")
+            .long("state-file")
+            .value_name("DM State Filename")
+            .long_help("The name of a file in which the maximum matching and the fine blocks are saved after the \
+            decomposition. If the file exists when dmdec starts, the key images and public keys in the input file \
+            beyond those in the saved state are treated as newly appended, and the decomposition is updated \
+            incrementally instead of being calculated from scratch. If the ring of any saved key image changed, \
+            e.g. because of different height or known spend options, the decomposition is calculated from scratch")
+    )
+    .arg(
+        Arg::new("spent_status_file")
+            .long("spent-status-file")
+            .value_name("Spent Status Filename")
+            .long_help("The name of an output file that will have the spent status of each public key. Each row has \
+            a public key index and its status: 0 if the output is possibly unspent, 1 if it is definitely spent and \
+            2 if it is definitely spent by a known key image, which follows as the third column")
+    )
+    .arg(
+        Arg::new("btf_file")
+            .long("btf-file")
+            .value_name("Block Triangular Form Filename")
+            .long_help("The name of an output file that will have the row and column permutations that put the key \
+            image x public key matrix into block upper triangular form, with the fine and coarse block boundaries, \
+            in the layout of the p, q, r, s, rr and cc arrays of cs_dmperm in CSparse")
+    )
+    .arg(
+        Arg::new("dag_file")
+            .long("dag-file")
+            .value_name("Block DAG Filename")
+            .long_help("The name of an output file that will have the partial order between the blocks of the fine \
+            decomposition, with the blocks numbered as in the fine decomposition file. There is an edge from block a \
+            to block b if a key image of a has a ring member in b. The depth of a block is the length of the longest \
+            path starting from it")
+    )
+    .arg(
+        Arg::new("verify_matching")
+            .long("verify-matching")
+            .takes_value(false)
+            .long_help("Verify that the matching used for the decomposition is maximum by checking that the König \
+            vertex cover calculated from it covers every edge and has the same size as the matching")
+    )
+    .arg(known_spends_arg())
+    .args(dictionary_args())
     .after_help("The dmdec command calculates the Dulmage-Mendelsohn decomposition \
     of a matrix described in a file. Each row in the file describes an edge. \
     The first two entries in each row are non-negative integers separated by a \
@@ -56,15 +103,27 @@ fn main() {
     let fine_decomposition_file = m.get_one::<String>("fine_decomposition_file").unwrap();
 
     let start_instant = Instant::now();
-    let (pk_indices, ki_indices, max_pk_index, max_ki_index) = read_edges(edge_file);
+    let (mut pk_indices, mut ki_indices, max_pk_index, max_ki_index) = read_edges_with_args(edge_file, &m);
     let end_instant = Instant::now();
     println!("Edge file read in {:?}", end_instant.duration_since(start_instant));
 
+    print!("{}", preprocess_edges_by_height(&m, &mut pk_indices, &mut ki_indices));
+
 
     let num_pks = (max_pk_index+1) as usize;
     let num_kis = (max_ki_index+1) as usize ;
     println!("Num keyimages = {}, Num public keys = {}", num_kis, num_pks);
 
+    let side_info = apply_known_spends_from_args(&m, &mut pk_indices, &mut ki_indices, |pk_indices, ki_indices| {
+        let base_g = build_txgraph(pk_indices.to_vec(), ki_indices.to_vec(), num_pks, num_kis);
+        let base_dmd = dm_decomposition(&base_g);
+        println!("Singletons without side information: {}", base_dmd.num_singletons());
+        base_dmd.traced_keyimages()
+    });
+    if let Some(side_info) = &side_info {
+        print!("{}", side_info.summary);
+    }
+
     // Each index in tx_ring_sets corresponds to one key image.
     // The vector at that index has the ring of public key indices.
     let mut tx_ring_sets: Vec<HashSet<u32>> = vec![HashSet::new(); num_kis];
@@ -82,110 +141,58 @@ fn main() {
     write_ring_sets(&tx_ring_sets, num_pks, pre_dmd_rings_file);
 
 
-    let data = vec![1_u8; pk_indices.len()];
-    let tripmat: TriMatBase<Vec<u32>, Vec<u8>> = TriMatBase::from_triplets(
-        (num_pks, num_kis),
-        pk_indices,
-        ki_indices,
-        data,
-    );
-    let g: CsMatBase<u8, u32,_,_,_> = tripmat.to_csc();
-    let pk_mates = cn_txgraph_maximum_matching(&g);
-
-
-    // Finding sets of reachable and unreachable public keys and key images
-    // from unmatched public keys (rows)
-    let mut num_matched_pks: u32 = 0;
-    let mut ki_mates = vec![u32::MAX; num_kis];
-    let mut queue = vec![u32::MAX; num_pks];
-    let mut reachable_pubkeys = vec![false; num_pks];
-    let mut reachable_keyimages = vec![false; num_kis];
-
-    let mut qhead = 0_usize;
-    let mut qtail = 0_usize;
-
-    for i in 0..num_pks {
-        if pk_mates[i] != u32::MAX {
-            ki_mates[pk_mates[i] as usize] = i as u32;
-            num_matched_pks += 1;
-        }
-        else {
-            reachable_pubkeys[i] = true;
-            queue[qtail] = i as u32;
-            qtail += 1;
-        }
-    }
-    println!("Matched {} out of {} rows (public keys)", num_matched_pks, num_pks);
-
-    let g_csr = g.to_csr();
-    let csr_row_ptr = g_csr.indptr();
-    let csr_row_ptr_slice = csr_row_ptr.as_slice().unwrap();
-    let csr_col_ptr_slice = g_csr.indices();
-
-    let mut q : u32;
-    while qhead < qtail {
-        let row_idx = queue[qhead]; // row_idx points to a pubkey
-        qhead += 1;
-        q = csr_row_ptr_slice[row_idx as usize];
-        while q < csr_row_ptr_slice[(row_idx+1) as usize] {
-
-            let col_idx = csr_col_ptr_slice[q as usize]; // This points to a keyimage
-            if reachable_keyimages[col_idx as usize] {
-                q += 1;
-                continue;
-            }
-            reachable_keyimages[col_idx as usize] = true;
-            
-            let row_mate = ki_mates[col_idx as usize]; //row_mate is a pubkey
-            if reachable_pubkeys[row_mate as usize] {
-                q += 1;
-                continue;
-            }
-            reachable_pubkeys[row_mate as usize] = true;
-            queue[qtail] = row_mate;
-            qtail += 1;
-
-            q += 1;
-        }
+    let g = build_txgraph(pk_indices, ki_indices, num_pks, num_kis);
+    let state_file = m.get_one::<String>("state_file");
+    let prev_state = state_file.and_then(|f| read_dm_state(f));
+    let start_instant = Instant::now();
+    let dmd = match &prev_state {
+        Some(prev) => {
+            println!("Previous state has {} keyimages, {} public keys and {} fine blocks", prev.num_kis, prev.num_pks, prev.fine_blocks.len());
+            dm_decomposition_incremental(&g, prev).unwrap_or_else(|| {
+                println!("Previous state is inconsistent with the edge file. Calculating decomposition from scratch");
+                dm_decomposition(&g)
+            })
+        },
+        None => dm_decomposition(&g),
+    };
+    let end_instant = Instant::now();
+    println!("DM decomposition calculated in {:?}", end_instant.duration_since(start_instant));
+    if let Some(state_file) = state_file {
+        write_dm_state(&DmState::from_decomposition(&g, &dmd), state_file);
     }
-
-    let mut num_unreachable_keyimages = num_kis;
-    for i in 0..num_kis {
-        if reachable_keyimages[i] {
-            num_unreachable_keyimages -= 1;
-        }
+    println!("Matched {} out of {} rows (public keys)", dmd.num_matched(), num_pks);
+    if m.contains_id("verify_matching") {
+        let start_instant = Instant::now();
+        assert!(verify_maximum_matching(&g, &dmd.pk_mates), "The matching is not a maximum matching");
+        let end_instant = Instant::now();
+        println!("Maximum matching verified with a vertex cover of size {} in {:?}", dmd.num_matched(), end_instant.duration_since(start_instant));
     }
 
-    let mut num_unreachable_pubkeys = num_pks;
-    for i in 0..num_pks {
-        if reachable_pubkeys[i] {
-            num_unreachable_pubkeys -= 1;
-        }
-    }
+    let num_unreachable_keyimages = dmd.reachable_keyimages.iter().filter(|r| !**r).count();
+    let num_unreachable_pubkeys = dmd.reachable_pubkeys.iter().filter(|r| !**r).count();
     println!("Number of unreachable pubkeys and keyimages = {} {}", num_unreachable_pubkeys, num_unreachable_keyimages);
 
-        // Fine decomposition graph
-    let mut fd_graph = DiGraphMap::<u32, ()>::new();
-
-    // Iterating over all public keys in the square submatrix
-    // and building the directed graph
-    for pk_idx in 0..num_pks {
-        if reachable_pubkeys[pk_idx] {
-            continue;
-        }
-        fd_graph.add_node(pk_idx as u32);
-        q = csr_row_ptr_slice[pk_idx];
-        while q < csr_row_ptr_slice[pk_idx+1] {
-            let ki_idx = csr_col_ptr_slice[q as usize]; // This points to a keyimage
-            if reachable_keyimages[ki_idx as usize] == false && ki_mates[ki_idx as usize] != pk_idx as u32 {
-                fd_graph.add_edge(ki_mates[ki_idx as usize], pk_idx as u32, ());
-            }
-            q += 1;
+    if let Some(spent_status_file) = m.get_one::<String>("spent_status_file") {
+        write_spent_statuses(&dmd, spent_status_file);
+    }
+    if let Some(btf_file) = m.get_one::<String>("btf_file") {
+        let btf = block_triangular_form(&g, &dmd);
+        write_block_triangular_form(&btf, btf_file);
+        println!("Block triangular form has {} blocks", btf.r.len() - 1);
+    }
+    if let Some(dag_file) = m.get_one::<String>("dag_file") {
+        let (dag_edges, depths) = fine_block_dag(&g, &dmd);
+        write_fine_block_dag(&dmd, &dag_edges, &depths, dag_file);
+        let mut depth_histogram: BTreeMap<u32, u32> = BTreeMap::new();
+        for depth in &depths {
+            *depth_histogram.entry(*depth).or_insert(0) += 1;
         }
+        println!("Edges between fine blocks = {}", dag_edges.len());
+        println!("Fine block depth histogram: {:?}", depth_histogram);
     }
 
-    // Finding strongly connected components
-    let pk_scc = tarjan_scc(&fd_graph);
+    let pk_mates = &dmd.pk_mates;
+    let pk_scc = &dmd.fine_blocks;
     println!("Number of blocks in fine decomposition: {}", pk_scc.len());
     let mut singletons = 0_u32;
     
@@ -209,7 +216,7 @@ fn main() {
         }
         let mut ki_set: HashSet<u32> = HashSet::new();
         let mut pk_set: HashSet<u32> = HashSet::new();
-        for pk in &comp_vec {
+        for pk in comp_vec {
             write!(fine_decomp_file_buf, "{} ", pk).expect(file_err_msg);
             let ki = pk_mates[*pk as usize];
             pk_set.insert(*pk);
@@ -228,6 +235,35 @@ fn main() {
 
     println!("Singletons (traceable keyimages): {}", singletons);
     println!("Closed set size histogram: {:?}", closed_set_size_histogram);
+    if let Some(side_info) = &side_info {
+        println!("Keyimages traced because of the known spends (knock-on) = {}",
+            side_info.knock_on_traced(&dmd.traced_keyimages())
+        );
+    }
 
     write_ring_sets(&tx_ring_sets, num_pks, post_dmd_rings_file);
 }
+
+// Writes the spent status of each public key and prints the counts
+fn write_spent_statuses(
+    dmd: &DmDecomposition,
+    filename: &str,
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let spent_status_file = File::create(filename).expect(file_err_msg);
+    let mut buf = BufWriter::new(spent_status_file);
+    let mut status_counts = [0_usize; 3];
+
+    for (pk, status) in dmd.spent_statuses().iter().enumerate() {
+        status_counts[status.code() as usize] += 1;
+        if *status == SpentStatus::KnownSpender {
+            writeln!(buf, "{} {} {}", pk, status.code(), dmd.pk_mates[pk]).expect(file_err_msg);
+        }
+        else {
+            writeln!(buf, "{} {}", pk, status.code()).expect(file_err_msg);
+        }
+    }
+    println!("Possibly unspent public keys = {}", status_counts[SpentStatus::PossiblyUnspent.code() as usize]);
+    println!("Definitely spent public keys with unknown spender = {}", status_counts[SpentStatus::DefinitelySpent.code() as usize]);
+    println!("Definitely spent public keys with known spender = {}", status_counts[SpentStatus::KnownSpender.code() as usize]);
+}
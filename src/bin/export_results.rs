@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_fine_decomposition, read_rings};
+use xmrtrace::interner::parse_hex_identifier;
+use xmrtrace::db::{keyimages_table, open_database, read_keyimages, read_outputs, write_attack_results, AttackResult,
+    MAIN_CHAIN, RESULT_COLUMNS};
+
+fn main() {
+    let cmd = Command::new("Attack Result Export")
+    .bin_name("export_results")
+    .arg(
+        Arg::new("database_file")
+            .value_name("Database Filename")
+            .required(true)
+            .long_help("The name of the SQLite database file from which the edges were exported")
+    )
+    .arg(
+        Arg::new("rings_file")
+            .value_name("Post-Attack Rings Filename")
+            .required(true)
+            .long_help("The name of the file containing the rings after an attack, e.g. the post-DM rings of dmdec or \
+            the rings written by cascade or cluster")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the SQL statements or CSV rows")
+    )
+    .arg(
+        Arg::new("fine_decomposition_file")
+            .long("fine-decomposition")
+            .value_name("Fine Decomposition Filename")
+            .long_help("The name of the file containing the closed sets written by dmdec. The closed set id of a key \
+            image is the position of its block in the file")
+    )
+    .arg(
+        Arg::new("attack")
+            .long("attack")
+            .value_name("Attack Name")
+            .default_value("dm")
+            .long_help("The name of the attack stored with each result")
+    )
+    .arg(
+        Arg::new("format")
+            .long("format")
+            .value_name("Output Format")
+            .value_parser(["sql", "csv"])
+            .default_value("sql")
+            .long_help("Write UPDATE statements for Postgres or CSV rows keyed by key image")
+    )
+    .arg(
+        Arg::new("apply")
+            .long("apply")
+            .takes_value(false)
+            .long_help("Also store the results in the keyimages table of the SQLite database")
+    )
+    .arg(
+        Arg::new("chain")
+            .long("chain")
+            .value_name("Chain Name")
+            .default_value(MAIN_CHAIN)
+            .long_help("The table prefix of the chain")
+    )
+    .after_help("The export_results command writes attack results into the keyimages table schema as the columns \
+    post_dm_ring_indices, traced_output, closed_set_id and attack, keyed by the key image. The key image and public \
+    key indices of the rings file are those of the edges exported by sqlite_db, i.e. the key image id minus one and \
+    the output id minus one. The ring indices are the global indices of the remaining ring members and the traced \
+    output is the global index of the only remaining ring member. The SQL output adds the columns if they are missing \
+    and updates the rows in a transaction. The CSV output has a header row and can be loaded with COPY into a \
+    staging table.");
+
+
+    let m = cmd.get_matches();
+    let db_fname = m.get_one::<String>("database_file").unwrap();
+    let rings_fname = m.get_one::<String>("rings_file").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+    let attack = m.get_one::<String>("attack").unwrap();
+    let chain = m.get_one::<String>("chain").unwrap();
+
+    let mut start_instant = Instant::now();
+    let mut conn = open_database(db_fname);
+    let records = read_keyimages(&conn, &keyimages_table(chain));
+    let output_indices: HashMap<i64, u64> = read_outputs(&conn, chain)
+        .into_iter()
+        .map(|((_, index), (id, _))| (id, index))
+        .collect();
+    let (tx_rings, _pk_to_ki_map) = read_rings(rings_fname);
+    let mut closed_set_ids: HashMap<u32, u64> = HashMap::new();
+    if let Some(fine_fname) = m.get_one::<String>("fine_decomposition_file") {
+        for (block_id, (_pks, kis)) in read_fine_decomposition(fine_fname).into_iter().enumerate() {
+            for ki in kis {
+                closed_set_ids.insert(ki, block_id as u64);
+            }
+        }
+    }
+    let mut end_instant = Instant::now();
+    println!("Database and result files read in {:?}", end_instant.duration_since(start_instant));
+
+    let records_by_id: HashMap<i64, usize> = records.iter().enumerate().map(|(i, r)| (r.id, i)).collect();
+    let mut results: Vec<AttackResult> = vec![];
+    let mut num_unknown_kis = 0_usize;
+    for (ki, ring) in tx_rings.iter().enumerate() {
+        let Some(record_index) = records_by_id.get(&(ki as i64 + 1)) else {
+            num_unknown_kis += 1;
+            continue;
+        };
+        let mut post_dm_ring_indices: Vec<u64> = ring.iter()
+            .map(|pk| *output_indices.get(&(*pk as i64 + 1)).expect("Public key index not in outputs table"))
+            .collect();
+        post_dm_ring_indices.sort_unstable();
+        let traced_output = if post_dm_ring_indices.len() == 1 { Some(post_dm_ring_indices[0]) } else { None };
+
+        // The key image is pasted into the SQL statements, so anything other
+        // than 64 hex characters is rejected
+        let image = &records[*record_index].image;
+        parse_hex_identifier(image).expect("Key image in the database is not 64 hex characters");
+
+        results.push(AttackResult {
+            image: image.clone(),
+            post_dm_ring_indices,
+            traced_output,
+            closed_set_id: closed_set_ids.get(&(ki as u32)).copied(),
+            attack: attack.clone(),
+        });
+    }
+
+    start_instant = Instant::now();
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+    let table = keyimages_table(chain);
+    let null_or = |value: Option<u64>| value.map(|v| v.to_string());
+    let pg_array = |indices: &[u64]| format!("{{{}}}", indices.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+
+    if m.get_one::<String>("format").unwrap() == "sql" {
+        writeln!(buf, "BEGIN;").expect(file_err_msg);
+        for (column, sql_type) in RESULT_COLUMNS {
+            writeln!(buf, "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {};", table, column, sql_type).expect(file_err_msg);
+        }
+        for result in &results {
+            writeln!(buf, "UPDATE {} SET post_dm_ring_indices = '{}', traced_output = {}, closed_set_id = {}, attack = '{}' WHERE image = '{}';",
+                table,
+                pg_array(&result.post_dm_ring_indices),
+                null_or(result.traced_output).unwrap_or("NULL".to_string()),
+                null_or(result.closed_set_id).unwrap_or("NULL".to_string()),
+                result.attack.replace('\'', "''"),
+                result.image,
+            ).expect(file_err_msg);
+        }
+        writeln!(buf, "COMMIT;").expect(file_err_msg);
+    }
+    else {
+        writeln!(buf, "image,post_dm_ring_indices,traced_output,closed_set_id,attack").expect(file_err_msg);
+        for result in &results {
+            writeln!(buf, "{},\"{}\",{},{},\"{}\"",
+                result.image,
+                pg_array(&result.post_dm_ring_indices),
+                null_or(result.traced_output).unwrap_or_default(),
+                null_or(result.closed_set_id).unwrap_or_default(),
+                result.attack.replace('"', "\"\""),
+            ).expect(file_err_msg);
+        }
+    }
+    buf.flush().expect(file_err_msg);
+    end_instant = Instant::now();
+    println!("Results written in {:?}", end_instant.duration_since(start_instant));
+
+    if m.contains_id("apply") {
+        let num_updated = write_attack_results(&mut conn, chain, &results);
+        println!("Keyimages updated in the database = {}", num_updated);
+    }
+    println!("Keyimages with results = {}", results.len());
+    println!("Traced keyimages = {}", results.iter().filter(|r| r.traced_output.is_some()).count());
+    println!("Key image indices not in the database (skipped) = {}", num_unknown_kis);
+}
@@ -0,0 +1,144 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_edges, read_indices, cascade_attack};
+use xmrtrace::dm::{build_txgraph, dm_decomposition};
+use xmrtrace::flooding::{remove_attacker_pks, sample_attacker_pks};
+
+fn main() {
+    let cmd = Command::new("Attacker-Owned Outputs Simulation")
+    .bin_name("flooding")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Input Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Results Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the number of traced rings for each attacker fraction")
+    )
+    .arg(
+        Arg::new("attacker_pks_file")
+            .long("attacker-pks")
+            .value_name("Attacker Public Keys Filename")
+            .conflicts_with("fractions")
+            .long_help("The name of the file containing the indices of the public keys owned by the attacker, one per row")
+    )
+    .arg(
+        Arg::new("fractions")
+            .long("fractions")
+            .value_name("Attacker Fractions")
+            .value_parser(clap::value_parser!(f64))
+            .use_value_delimiter(true)
+            .multiple_values(true)
+            .default_value("0,0.1,0.2,0.3,0.4,0.5")
+            .long_help("A comma separated list of fractions of public keys owned by the attacker. \
+            For each fraction, the attacker-owned public keys are sampled at random")
+    )
+    .arg(
+        Arg::new("seed")
+            .long("seed")
+            .value_name("Seed")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .long_help("The seed of the random number generator used to sample attacker-owned public keys")
+    )
+    .after_help("The flooding command simulates an attacker who owns a set of outputs and removes them from the rings \
+    of other key images before running the cascade attack and the DM decomposition. Rings whose members are all \
+    owned by the attacker are left unchanged. Each row of the output file has the fraction of public keys owned by \
+    the attacker, the number of attacker-owned public keys, the number of ring members removed, the number of rings \
+    with only attacker-owned members, the number of rings traced by the cascade attack and the number of key images \
+    traced by the DM decomposition, separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let input_fname = m.get_one::<String>("input_file").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+    let seed = *m.get_one::<u64>("seed").unwrap();
+
+    let start_instant = Instant::now();
+    let (pk_indices, ki_indices, max_pk_index, max_ki_index) = read_edges(input_fname);
+    let end_instant = Instant::now();
+    println!("Edge file read in {:?}", end_instant.duration_since(start_instant));
+
+    let num_pks = (max_pk_index+1) as usize;
+    let num_kis = (max_ki_index+1) as usize ;
+    println!("Num keyimages = {}, Num public keys = {}", num_kis, num_pks);
+
+    let mut tx_rings: Vec<Vec<u32>> = vec![vec![]; num_kis];
+    for (pk, ki) in pk_indices.iter().zip(ki_indices.iter()) {
+        tx_rings[*ki as usize].push(*pk);
+    }
+
+    // Each scenario is a fraction label and the attacker-owned public keys
+    let scenarios: Vec<(f64, Vec<bool>)> = match m.get_one::<String>("attacker_pks_file") {
+        Some(fname) => {
+            let mut attacker_owned = vec![false; num_pks];
+            for pk in read_indices(fname) {
+                if (pk as usize) < num_pks {
+                    attacker_owned[pk as usize] = true;
+                }
+            }
+            let num_owned = attacker_owned.iter().filter(|o| **o).count();
+            vec![(num_owned as f64 / num_pks as f64, attacker_owned)]
+        },
+        None => m.get_many::<f64>("fractions").unwrap()
+            .enumerate()
+            .map(|(i, f)| {
+                assert!((0.0..=1.0).contains(f), "Fractions must be between 0 and 1");
+                (*f, sample_attacker_pks(num_pks, *f, seed.wrapping_add(i as u64)))
+            })
+            .collect(),
+    };
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    for (fraction, attacker_owned) in &scenarios {
+        let start_instant = Instant::now();
+        let num_owned = attacker_owned.iter().filter(|o| **o).count();
+        let mut reduced_rings = tx_rings.clone();
+        let summary = remove_attacker_pks(&mut reduced_rings, attacker_owned);
+
+        let mut reduced_pk_indices: Vec<u32> = vec![];
+        let mut reduced_ki_indices: Vec<u32> = vec![];
+        let mut pk_to_ki_map: Vec<Vec<u32>> = vec![vec![]; num_pks];
+        for (ki, ring) in reduced_rings.iter().enumerate() {
+            for pk in ring {
+                reduced_pk_indices.push(*pk);
+                reduced_ki_indices.push(ki as u32);
+                pk_to_ki_map[*pk as usize].push(ki as u32);
+            }
+        }
+
+        let num_traced_cascade = cascade_attack(&mut reduced_rings, &pk_to_ki_map, usize::MAX);
+        let g = build_txgraph(reduced_pk_indices, reduced_ki_indices, num_pks, num_kis);
+        let num_traced_dm = dm_decomposition(&g).num_singletons();
+        let end_instant = Instant::now();
+
+        println!("Attacker fraction {}: {} public keys owned, {} ring members removed, {} attacker rings, \
+            {} rings traced by cascade attack, {} keyimages traced by DM decomposition. Time taken = {:?}",
+            fraction,
+            num_owned,
+            summary.removed_members,
+            summary.attacker_rings,
+            num_traced_cascade,
+            num_traced_dm,
+            end_instant.duration_since(start_instant),
+        );
+        writeln!(buf, "{} {} {} {} {} {}",
+            fraction,
+            num_owned,
+            summary.removed_members,
+            summary.attacker_rings,
+            num_traced_cascade,
+            num_traced_dm,
+        ).expect(file_err_msg);
+    }
+}
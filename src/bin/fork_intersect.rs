@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_edges, write_edges};
+use xmrtrace::forks::intersect_fork_rings;
+use xmrtrace::interner::{dictionary_args, read_dictionary, read_hex_edges, write_dictionary};
+
+fn main() {
+    let cmd = Command::new("Hard Fork Ring Intersection")
+    .bin_name("fork_intersect")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Main Chain Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges of the main chain")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Reduced Edge Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the edges of the main chain after ring intersection")
+    )
+    .arg(
+        Arg::new("fork_files")
+            .value_name("Fork Edge Filenames")
+            .required(true)
+            .multiple_values(true)
+            .long_help("The names of the files containing the edges of each fork. The key image and public key indices \
+            must identify the same key images and outputs as in the main chain edge file")
+    )
+    .arg(
+        Arg::new("stats_file")
+            .long("stats-file")
+            .value_name("Statistics Filename")
+            .long_help("The name of an output file that will have the statistics of each fork")
+    )
+    .args(dictionary_args())
+    .after_help("The fork_intersect command intersects the rings of key images that appear both in the main chain \
+    and in one or more hard forks. The real spend of such a key image is in every ring that it signs. If the rings \
+    of a key image have no common member, its main chain ring is left unchanged and the key image is counted as an \
+    anomaly. Each row of the statistics file has the fork edge filename, the number of shared key images, the number \
+    of reduced rings, the number of removed ring members, the number of rings reduced to a single member and the \
+    number of empty intersections, separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let input_fname = m.get_one::<String>("input_file").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+
+    // Dictionaries of key images and public keys when the edge files have
+    // hexadecimal identifiers
+    let ki_dict_fname = m.get_one::<String>("keyimage_dict");
+    let pk_dict_fname = m.get_one::<String>("pubkey_dict");
+    let mut interners = ki_dict_fname.zip(pk_dict_fname)
+        .map(|(ki_dict, pk_dict)| (read_dictionary(ki_dict), read_dictionary(pk_dict)));
+    let mut read_edge_file = |fname: &String| match &mut interners {
+        Some((ki_interner, pk_interner)) => read_hex_edges(fname, ki_interner, pk_interner),
+        None => read_edges(fname),
+    };
+
+    let start_instant = Instant::now();
+    let (pk_indices, ki_indices, _max_pk_index, max_ki_index) = read_edge_file(input_fname);
+    let end_instant = Instant::now();
+    println!("Main chain edge file read in {:?}", end_instant.duration_since(start_instant));
+
+    let num_kis = (max_ki_index+1) as usize;
+    let mut tx_ring_sets: Vec<HashSet<u32>> = vec![HashSet::new(); num_kis];
+    for (pk, ki) in pk_indices.iter().zip(ki_indices.iter()) {
+        tx_ring_sets[*ki as usize].insert(*pk);
+    }
+    let num_initial_traced = tx_ring_sets.iter().filter(|ring| ring.len() == 1).count();
+    println!("Num keyimages = {}, Zero-mixin rings = {}", num_kis, num_initial_traced);
+
+    let file_err_msg = "Unable to create or write to file";
+    let mut stats_buf = m.get_one::<String>("stats_file")
+        .map(|f| BufWriter::new(File::create(f).expect(file_err_msg)));
+
+    for fork_fname in m.get_many::<String>("fork_files").unwrap() {
+        let start_instant = Instant::now();
+        let (fork_pk_indices, fork_ki_indices, _, _) = read_edge_file(fork_fname);
+        let stats = intersect_fork_rings(&mut tx_ring_sets, &fork_pk_indices, &fork_ki_indices);
+        let end_instant = Instant::now();
+
+        println!("Fork {} processed in {:?}", fork_fname, end_instant.duration_since(start_instant));
+        println!("Shared keyimages = {}", stats.shared_keyimages);
+        println!("Reduced rings = {}", stats.reduced_rings);
+        println!("Removed ring members = {}", stats.removed_members);
+        println!("Intersections of size 1 = {}", stats.traced_rings());
+        println!("Empty intersections (main chain ring kept) = {}", stats.empty_intersections);
+        println!("Intersection size histogram: {:?}", stats.intersection_size_histogram);
+
+        if let Some(buf) = &mut stats_buf {
+            writeln!(buf, "{} {} {} {} {} {}",
+                fork_fname,
+                stats.shared_keyimages,
+                stats.reduced_rings,
+                stats.removed_members,
+                stats.traced_rings(),
+                stats.empty_intersections,
+            ).expect(file_err_msg);
+        }
+    }
+
+    let num_traced = tx_ring_sets.iter().filter(|ring| ring.len() == 1).count();
+    println!("Zero-mixin rings after intersection with all forks = {}", num_traced);
+    write_edges(tx_ring_sets, output_fname);
+
+    if let (Some((ki_interner, pk_interner)), Some(ki_dict), Some(pk_dict)) = (&interners, ki_dict_fname, pk_dict_fname) {
+        write_dictionary(ki_interner, ki_dict);
+        write_dictionary(pk_interner, pk_dict);
+    }
+}
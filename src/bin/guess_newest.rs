@@ -0,0 +1,103 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use std::collections::BTreeMap;
+use clap::{Arg, Command};
+
+use xmrtrace::read_rings;
+use xmrtrace::read_heights;
+use xmrtrace::guess_newest::{guess_newest, GuessConfidence, DEFAULT_RECENT_WINDOW};
+
+fn main() {
+    let default_recent_window = DEFAULT_RECENT_WINDOW.to_string();
+    let cmd = Command::new("CryptoNote Guess-Newest Heuristic")
+    .bin_name("guess_newest")
+    .arg(
+        Arg::new("rings_file")
+            .value_name("Input Rings Filename")
+            .required(true)
+            .long_help("The name of the file containing a list of rings. This can be the output of dmdec, cascade or cluster \
+            so that the heuristic is applied to the rings left over by the provable attacks")
+    )
+    .arg(
+        Arg::new("pk_heights_file")
+            .value_name("Public Key Creation Heights Filename")
+            .required(true)
+            .long_help("The name of the file containing the block height at which each public key was created")
+    )
+    .arg(
+        Arg::new("ki_heights_file")
+            .value_name("Key Image Spend Heights Filename")
+            .required(true)
+            .long_help("The name of the file containing the block height at which each key image appeared")
+    )
+    .arg(
+        Arg::new("output_file")
+            .value_name("Guesses Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the guessed public key and confidence for each key image")
+    )
+    .arg(
+        Arg::new("recent_window")
+            .long("recent-window")
+            .value_name("Recent Window")
+            .value_parser(clap::value_parser!(u32))
+            .default_value(&default_recent_window)
+            .long_help("The number of blocks before the spend height within which a uniquely newest member is a high confidence guess")
+    )
+    .after_help("The guess_newest command marks the most recently created member of each ring as the spent output. \
+    Ring members created after the spend height of their key image are skipped and counted. \
+    Each row of a heights file has an index followed by a block height, separated by a space. \
+    Each row of the output file has a key image index, the guessed public key index and the confidence bucket \
+    (certain, high, medium, low or unknown) separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let rings_fname = m.get_one::<String>("rings_file").unwrap();
+    let pk_heights_fname = m.get_one::<String>("pk_heights_file").unwrap();
+    let ki_heights_fname = m.get_one::<String>("ki_heights_file").unwrap();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+    let recent_window = *m.get_one::<u32>("recent_window").unwrap();
+
+    let mut start_instant = Instant::now();
+    let (tx_rings, pk_to_ki_map) = read_rings(rings_fname);
+    let mut end_instant = Instant::now();
+    println!("Rings file read in {:?}", end_instant.duration_since(start_instant));
+    println!("Num keyimages = {}, Num public keys = {}", tx_rings.len(), pk_to_ki_map.len());
+
+    start_instant = Instant::now();
+    let pk_heights = read_heights(pk_heights_fname);
+    let ki_heights = read_heights(ki_heights_fname);
+    end_instant = Instant::now();
+    println!("Heights files read in {:?}", end_instant.duration_since(start_instant));
+
+    start_instant = Instant::now();
+    let (guesses, num_future_members) = guess_newest(&tx_rings, &pk_heights, &ki_heights, recent_window);
+    end_instant = Instant::now();
+    println!("Guesses made in {:?}", end_instant.duration_since(start_instant));
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+    let mut confidence_histogram: BTreeMap<GuessConfidence, u32> = BTreeMap::new();
+
+    for (ki, (pk, confidence)) in guesses.iter().enumerate() {
+        confidence_histogram.entry(*confidence).and_modify(|c| *c += 1).or_insert(1);
+        if *pk == u32::MAX {
+            writeln!(buf, "{} - {}", ki, confidence).expect(file_err_msg);
+        }
+        else {
+            writeln!(buf, "{} {} {}", ki, pk, confidence).expect(file_err_msg);
+        }
+    }
+
+    for (confidence, count) in &confidence_histogram {
+        println!("Guesses with {} confidence = {}", confidence, count);
+    }
+    let num_heuristic_guesses: u32 = confidence_histogram.iter()
+        .filter(|(c, _)| **c != GuessConfidence::Certain && **c != GuessConfidence::Unknown)
+        .map(|(_, n)| *n)
+        .sum();
+    println!("Ring members created after the spend height (skipped) = {}", num_future_members);
+    println!("Rings guessed by heuristic (not traced by provable attacks) = {}", num_heuristic_guesses);
+}
@@ -0,0 +1,88 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::read_lines;
+use xmrtrace::interner::{hex_identifier, hex_to_bytes};
+use xmrtrace::txprefix::parse_tx_prefix;
+
+fn main() {
+    let cmd = Command::new("Ring Extraction from Binary Transactions")
+    .bin_name("ingest_blobs")
+    .arg(
+        Arg::new("output_file")
+            .value_name("Output Edge Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the edges of the rings")
+    )
+    .arg(
+        Arg::new("input_files")
+            .value_name("Transaction Blob Filenames")
+            .required(true)
+            .multiple_values(true)
+            .long_help("The names of the files containing serialized transactions. Each row of a file has a \
+            hexadecimal transaction blob as its last field, optionally preceded by other fields such as the height")
+    )
+    .arg(
+        Arg::new("binary")
+            .long("binary")
+            .takes_value(false)
+            .long_help("Read each input file as a single raw binary transaction blob instead of hexadecimal rows")
+    )
+    .after_help("The ingest_blobs command parses the prefixes of serialized transactions and extracts the rings of \
+    their key inputs without the JSON layer of monerod. The relative key offsets of each ring are converted to global \
+    output indices. Each row of the output file has a hexadecimal key image, the amount and the global index of a \
+    ring member, separated by spaces. RingCT ring members have the amount 0. The output is in the same format as \
+    that of ingest_json.");
+
+
+    let m = cmd.get_matches();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+    let binary = m.contains_id("binary");
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    let mut num_txs = 0_usize;
+    let mut num_invalid = 0_usize;
+    let mut num_inputs = 0_usize;
+    let mut num_edges = 0_usize;
+    let start_instant = Instant::now();
+
+    for input_fname in m.get_many::<String>("input_files").unwrap() {
+        let blobs: Vec<Vec<u8>> = if binary {
+            vec![std::fs::read(input_fname).expect("Unable to read input file")]
+        }
+        else {
+            read_lines(input_fname).expect("Unable to open input file")
+                .map_while(Result::ok)
+                .filter_map(|line| line.split_whitespace().last().map(|blob| hex_to_bytes(blob).unwrap_or_default()))
+                .collect()
+        };
+
+        for blob in blobs {
+            num_txs += 1;
+            let Some((prefix, _)) = parse_tx_prefix(&blob) else {
+                num_invalid += 1;
+                continue;
+            };
+            for input in prefix.ring_inputs() {
+                num_inputs += 1;
+                let key_image = hex_identifier(&input.key_image);
+                for index in &input.key_indices {
+                    writeln!(buf, "{} {} {}", key_image, input.amount, index).expect(file_err_msg);
+                    num_edges += 1;
+                }
+            }
+        }
+    }
+    let end_instant = Instant::now();
+
+    println!("Transaction blobs processed in {:?}", end_instant.duration_since(start_instant));
+    println!("Transactions = {}", num_txs);
+    println!("Invalid or unsupported blobs (skipped) = {}", num_invalid);
+    println!("Key inputs = {}", num_inputs);
+    println!("Edges = {}", num_edges);
+}
@@ -0,0 +1,238 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::read_lines;
+use xmrtrace::chains::{chain_arg, chain_profile};
+use xmrtrace::ingest::{block_from_json, output_amount_key, ring_inputs, transactions_by_hash, tx_outputs, OutputIndex};
+use xmrtrace::interner::hex_identifier;
+
+const KEYIMAGES_FNAME: &str = "keyimages.txt";
+const OUTPUTS_FNAME: &str = "outputs.txt";
+const EDGES_FNAME: &str = "edges.txt";
+const PROGRESS_FNAME: &str = "progress.txt";
+
+fn main() {
+    let cmd = Command::new("Monero Block Dump Ingestion")
+    .bin_name("ingest_blocks")
+    .arg(
+        Arg::new("dump_dir")
+            .value_name("Dump Directory")
+            .required(true)
+            .long_help("The directory containing the saved RPC responses. The getblock response of height h is in \
+            block_h.json and the gettransactions response for the transactions of the block is in txs_h.json")
+    )
+    .arg(
+        Arg::new("output_dir")
+            .value_name("Output Directory")
+            .required(true)
+            .long_help("The directory in which the key image, output, edge and progress files are written")
+    )
+    .arg(
+        Arg::new("from_height")
+            .long("from-height")
+            .value_name("From Height")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .long_help("The first block height to ingest when there is no saved progress. Global output indices are \
+            counted from the genesis block, so a fresh ingestion must start at height 0")
+    )
+    .arg(
+        Arg::new("to_height")
+            .long("to-height")
+            .value_name("To Height")
+            .value_parser(clap::value_parser!(u64))
+            .long_help("The last block height to ingest. By default, blocks are ingested until a block file is missing")
+    )
+    .arg(
+        Arg::new("checkpoint_every")
+            .long("checkpoint-every")
+            .value_name("Checkpoint Interval")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("1000")
+            .long_help("The number of blocks after which the progress is saved")
+    )
+    .arg(
+        chain_arg("The chain whose ring size rules are checked during the ingestion. Small rings spending \
+            non-RingCT amounts before RingCT was required are not counted as violations")
+            .default_value("monero")
+    )
+    .after_help("The ingest_blocks command reads saved getblock and gettransactions responses of monerod in height \
+    order and writes the transaction graph. Each row of keyimages.txt has a key image index, the block height and the \
+    hexadecimal key image. Each row of outputs.txt has a public key index, the block height, 1 for coinbase outputs \
+    and 0 otherwise, the amount under which the global index is counted (0 for RingCT outputs), the global index and \
+    the hexadecimal public key. edges.txt has one edge per row in the format read by the other commands. \
+    keyimages.txt and outputs.txt can be used as the heights files of the other commands. \
+    The ingestion resumes from the height saved in progress.txt. Ring members whose global indices are not in \
+    outputs.txt are skipped and counted.");
+
+
+    let m = cmd.get_matches();
+    let dump_dir = Path::new(m.get_one::<String>("dump_dir").unwrap());
+    let output_dir = Path::new(m.get_one::<String>("output_dir").unwrap());
+    let to_height = m.get_one::<u64>("to_height").copied().unwrap_or(u64::MAX);
+    let checkpoint_every = *m.get_one::<u64>("checkpoint_every").unwrap();
+    let profile = chain_profile(m.get_one::<String>("chain").unwrap());
+
+    let ki_path = output_dir.join(KEYIMAGES_FNAME);
+    let out_path = output_dir.join(OUTPUTS_FNAME);
+    let edge_path = output_dir.join(EDGES_FNAME);
+    let progress_path = output_dir.join(PROGRESS_FNAME);
+
+    // The progress file has the next height to ingest and the lengths in
+    // bytes of the key image, output and edge files at that height.
+    // Anything written after the last checkpoint is discarded.
+    let progress = read_progress(&progress_path);
+    let mut height = match progress {
+        Some((next_height, _)) => next_height,
+        None => {
+            // Global indices are assigned by counting the outputs of each
+            // amount, which is only possible from the genesis block
+            let from_height = *m.get_one::<u64>("from_height").unwrap();
+            assert!(from_height == 0, "No saved progress in {}: a fresh ingestion must start at height 0", output_dir.display());
+            from_height
+        }
+    };
+    let file_lengths = progress.map(|(_, lengths)| lengths).unwrap_or([0, 0, 0]);
+    let file_err_msg = "Unable to create or write to file";
+    for (path, length) in [&ki_path, &out_path, &edge_path].iter().zip(file_lengths) {
+        let file = OpenOptions::new().create(true).write(true).truncate(false).open(path).expect(file_err_msg);
+        file.set_len(length).expect(file_err_msg);
+    }
+
+    let start_instant = Instant::now();
+    let mut output_index = OutputIndex::new();
+    if let Ok(lines) = read_lines(&out_path) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            assert!(items.len() > 4);
+            let pk_index = items[0].parse::<u32>().unwrap();
+            let amount_key = items[3].parse::<u64>().unwrap();
+            let global_index = items[4].parse::<u64>().unwrap();
+            output_index.insert(amount_key, global_index, pk_index);
+        }
+    }
+    let mut num_kis = read_lines(&ki_path).map(|lines| lines.count()).unwrap_or(0) as u32;
+    let end_instant = Instant::now();
+    println!("Resuming at height {} with {} keyimages and {} outputs. State loaded in {:?}",
+        height,
+        num_kis,
+        output_index.num_outputs(),
+        end_instant.duration_since(start_instant),
+    );
+
+    let open_append = |path: &Path| BufWriter::new(OpenOptions::new().append(true).open(path).expect(file_err_msg));
+    let mut ki_buf = open_append(&ki_path);
+    let mut out_buf = open_append(&out_path);
+    let mut edge_buf = open_append(&edge_path);
+
+    let mut num_blocks = 0_u64;
+    let mut num_unknown_members = 0_usize;
+    let mut num_invalid_ring_sizes = 0_usize;
+    let start_instant = Instant::now();
+
+    while height <= to_height {
+        let block_path = dump_dir.join(format!("block_{}.json", height));
+        let Ok(block_file) = File::open(&block_path) else {
+            println!("Block file {} not found", block_path.display());
+            break;
+        };
+        let doc: serde_json::Value = serde_json::from_reader(BufReader::new(block_file)).expect("Invalid block file");
+        let block = block_from_json(&doc);
+
+        let tx_hashes: Vec<&str> = block["tx_hashes"].as_array()
+            .map(|hashes| hashes.iter().map(|h| h.as_str().expect("Invalid transaction hash")).collect())
+            .unwrap_or_default();
+        let mut txs = if tx_hashes.is_empty() {
+            Default::default()
+        }
+        else {
+            let txs_path = dump_dir.join(format!("txs_{}.json", height));
+            let txs_file = File::open(&txs_path).expect("Unable to open transactions file");
+            let txs_doc: serde_json::Value = serde_json::from_reader(BufReader::new(txs_file)).expect("Invalid transactions file");
+            transactions_by_hash(&txs_doc)
+        };
+
+        let miner_tx = &block["miner_tx"];
+        let block_txs = std::iter::once((miner_tx.clone(), true))
+            .chain(tx_hashes.iter().map(|h| (txs.remove(*h).expect("Transaction missing from transactions file"), false)));
+
+        for (tx, coinbase) in block_txs {
+            // A transaction cannot spend its own outputs, so its inputs are
+            // processed before its outputs
+            for input in ring_inputs(&tx) {
+                let dust = input.amount != 0 && profile.dust_exception_applies(height);
+                if !profile.ring_size_is_valid(height, input.key_indices.len()) && !dust {
+                    num_invalid_ring_sizes += 1;
+                }
+                let ki = num_kis;
+                num_kis += 1;
+                writeln!(ki_buf, "{} {} {}", ki, height, hex_identifier(&input.key_image)).expect(file_err_msg);
+                for global_index in &input.key_indices {
+                    match output_index.pk_index(input.amount, *global_index) {
+                        Some(pk) => writeln!(edge_buf, "{} {}", ki, pk).expect(file_err_msg),
+                        None => num_unknown_members += 1,
+                    }
+                }
+            }
+
+            let tx_version = tx["version"].as_u64().expect("Invalid transaction version");
+            for (amount, pubkey) in tx_outputs(&tx) {
+                let amount_key = output_amount_key(tx_version, amount);
+                let (pk, global_index) = output_index.add_output(amount_key);
+                writeln!(out_buf, "{} {} {} {} {} {}",
+                    pk,
+                    height,
+                    coinbase as u8,
+                    amount_key,
+                    global_index,
+                    hex_identifier(&pubkey),
+                ).expect(file_err_msg);
+            }
+        }
+
+        height += 1;
+        num_blocks += 1;
+        if num_blocks.is_multiple_of(checkpoint_every) {
+            write_progress(&progress_path, height, [&mut ki_buf, &mut out_buf, &mut edge_buf]);
+            println!("Checkpoint at height {}", height);
+        }
+    }
+    write_progress(&progress_path, height, [&mut ki_buf, &mut out_buf, &mut edge_buf]);
+    let end_instant = Instant::now();
+
+    println!("Ingested {} blocks in {:?}", num_blocks, end_instant.duration_since(start_instant));
+    println!("Next height = {}", height);
+    println!("Num keyimages = {}, Num outputs = {}", num_kis, output_index.num_outputs());
+    println!("Ring members with unknown outputs (skipped) = {}", num_unknown_members);
+    println!("Rings violating the {} ring size rules = {}", profile.name, num_invalid_ring_sizes);
+}
+
+fn read_progress(
+    path: &Path,
+) -> Option<(u64, [u64; 3])> {
+    let line = read_lines(path).ok()?.next()?.ok()?;
+    let items: Vec<u64> = line.split_whitespace().map(|x| x.parse::<u64>().unwrap()).collect();
+    assert_eq!(items.len(), 4);
+    Some((items[0], [items[1], items[2], items[3]]))
+}
+
+// Flushes the output files and saves the next height with their lengths
+fn write_progress(
+    path: &Path,
+    next_height: u64,
+    bufs: [&mut BufWriter<File>; 3],
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let mut lengths = [0_u64; 3];
+    for (buf, length) in bufs.into_iter().zip(lengths.iter_mut()) {
+        buf.flush().expect(file_err_msg);
+        buf.get_ref().sync_all().expect(file_err_msg);
+        *length = buf.get_ref().metadata().expect(file_err_msg).len();
+    }
+    let mut progress_file = File::create(path).expect(file_err_msg);
+    writeln!(progress_file, "{} {} {} {}", next_height, lengths[0], lengths[1], lengths[2]).expect(file_err_msg);
+}
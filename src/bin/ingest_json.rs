@@ -0,0 +1,66 @@
+use std::{fs::File, io::{BufReader, BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::ingest::{ring_inputs, transactions_from_json};
+use xmrtrace::interner::hex_identifier;
+
+fn main() {
+    let cmd = Command::new("Ring Extraction from JSON Transactions")
+    .bin_name("ingest_json")
+    .arg(
+        Arg::new("output_file")
+            .value_name("Output Edge Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the edges of the rings")
+    )
+    .arg(
+        Arg::new("input_files")
+            .value_name("JSON Transaction Filenames")
+            .required(true)
+            .multiple_values(true)
+            .long_help("The names of the files containing transactions as JSON. Each file can have a gettransactions \
+            response, an array of transactions or a single transaction")
+    )
+    .after_help("The ingest_json command extracts the rings of the key inputs of transactions decoded as JSON, as \
+    returned by the gettransactions RPC with decode_as_json. The relative key offsets of each ring are converted to \
+    global output indices. Each row of the output file has a hexadecimal key image, the amount and the global index \
+    of a ring member, separated by spaces. RingCT ring members have the amount 0.");
+
+
+    let m = cmd.get_matches();
+    let output_fname = m.get_one::<String>("output_file").unwrap();
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(output_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    let mut num_txs = 0_usize;
+    let mut num_inputs = 0_usize;
+    let mut num_edges = 0_usize;
+    let start_instant = Instant::now();
+
+    for input_fname in m.get_many::<String>("input_files").unwrap() {
+        let input_file = File::open(input_fname).expect("Unable to open input file");
+        let doc: serde_json::Value = serde_json::from_reader(BufReader::new(input_file)).expect("Invalid JSON file");
+
+        for tx in transactions_from_json(&doc) {
+            num_txs += 1;
+            for input in ring_inputs(&tx) {
+                num_inputs += 1;
+                let key_image = hex_identifier(&input.key_image);
+                for index in &input.key_indices {
+                    writeln!(buf, "{} {} {}", key_image, input.amount, index).expect(file_err_msg);
+                    num_edges += 1;
+                }
+            }
+        }
+    }
+    let end_instant = Instant::now();
+
+    println!("JSON files processed in {:?}", end_instant.duration_since(start_instant));
+    println!("Transactions = {}", num_txs);
+    println!("Key inputs = {}", num_inputs);
+    println!("Edges = {}", num_edges);
+}
@@ -0,0 +1,137 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_rings, read_fine_decomposition};
+use xmrtrace::matchings::{BlockGraph, MAX_PERMANENT_SIZE, exact_edge_probabilities, sampled_edge_probabilities};
+
+fn main() {
+    let cmd = Command::new("Spend Probabilities in DM Fine Decomposition Blocks")
+    .bin_name("marginals")
+    .arg(
+        Arg::new("post_dmd_rings_file")
+            .value_name("Post DM Decomposition Rings Filename")
+            .required(true)
+            .long_help("The name of the file containing a list of rings after the DM decomposition")
+    )
+    .arg(
+        Arg::new("fine_decomposition_file")
+            .value_name("Fine Decomposition Filename")
+            .required(true)
+            .long_help("The name of the file containing the closed sets in the DM fine decomposition")
+    )
+    .arg(
+        Arg::new("probability_file")
+            .value_name("Probability Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have the spend probability of each edge in the blocks")
+    )
+    .arg(
+        Arg::new("exact_limit")
+            .long("exact-limit")
+            .value_name("Exact Block Size Limit")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("12")
+            .long_help("Blocks with at most this many key images get exact probabilities calculated using permanents. The limit can be at most 22. \
+            The probabilities of larger blocks are estimated by sampling")
+    )
+    .arg(
+        Arg::new("num_steps")
+            .long("num-steps")
+            .value_name("Number of Steps")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("1000000")
+            .long_help("The number of Markov chain steps used to estimate the probabilities of a large block")
+    )
+    .arg(
+        Arg::new("burn_in")
+            .long("burn-in")
+            .value_name("Burn-in Steps")
+            .value_parser(clap::value_parser!(usize))
+            .default_value("100000")
+            .long_help("The number of initial Markov chain steps that are discarded")
+    )
+    .arg(
+        Arg::new("seed")
+            .long("seed")
+            .value_name("Seed")
+            .value_parser(clap::value_parser!(u64))
+            .default_value("0")
+            .long_help("The seed of the random number generator used for sampling")
+    )
+    .after_help("The marginals command calculates, for each block of the DM fine decomposition, the probability \
+    that a key image spends a public key assuming that all perfect matchings of the block are equally likely. \
+    Each row of the output file has a key image index, a public key index and the probability separated by spaces.");
+
+
+    let m = cmd.get_matches();
+    let rings_fname = m.get_one::<String>("post_dmd_rings_file").unwrap();
+    let fine_decomp_fname = m.get_one::<String>("fine_decomposition_file").unwrap();
+    let prob_fname = m.get_one::<String>("probability_file").unwrap();
+    let exact_limit = *m.get_one::<usize>("exact_limit").unwrap();
+    assert!(exact_limit <= MAX_PERMANENT_SIZE, "Exact block size limit must be at most {}", MAX_PERMANENT_SIZE);
+    let num_steps = *m.get_one::<usize>("num_steps").unwrap();
+    let burn_in = *m.get_one::<usize>("burn_in").unwrap();
+    let seed = *m.get_one::<u64>("seed").unwrap();
+
+    let mut start_instant = Instant::now();
+    let (tx_rings, _pk_to_ki_map) = read_rings(rings_fname);
+    let blocks = read_fine_decomposition(fine_decomp_fname);
+    let mut end_instant = Instant::now();
+    println!("Rings and fine decomposition files read in {:?}", end_instant.duration_since(start_instant));
+    println!("Number of blocks in fine decomposition: {}", blocks.len());
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(prob_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    let mut num_exact = 0_u32;
+    let mut num_sampled = 0_u32;
+    let mut num_without_matching = 0_u32;
+    start_instant = Instant::now();
+
+    for (b, (pks, kis)) in blocks.iter().enumerate() {
+        let block = BlockGraph::new(pks, kis, &tx_rings);
+
+        let probs = if block.size() <= exact_limit {
+            num_exact += 1;
+            exact_edge_probabilities(&block)
+        }
+        else {
+            num_sampled += 1;
+            let block_start_instant = Instant::now();
+            let result = sampled_edge_probabilities(&block, num_steps, burn_in, seed.wrapping_add(b as u64));
+            if let Some((_, num_perfect)) = &result {
+                println!("Block {} of size {} sampled with {} of {} steps in perfect matchings in {:?}",
+                    b,
+                    block.size(),
+                    num_perfect,
+                    num_steps,
+                    Instant::now().duration_since(block_start_instant),
+                );
+            }
+            result.map(|(p, _)| p)
+        };
+
+        match probs {
+            Some(probs) => {
+                for (i, nbrs) in block.adj.iter().enumerate() {
+                    for (k, j) in nbrs.iter().enumerate() {
+                        writeln!(buf, "{} {} {}", block.kis[i], block.pks[*j], probs[i][k]).expect(file_err_msg);
+                    }
+                }
+            },
+            None => {
+                num_without_matching += 1;
+                println!("Block {} of size {} has no perfect matching", b, block.size());
+            },
+        }
+    }
+    end_instant = Instant::now();
+
+    println!("Probabilities calculated in {:?}", end_instant.duration_since(start_instant));
+    println!("Blocks with exact probabilities = {}", num_exact);
+    println!("Blocks with sampled probabilities = {}", num_sampled);
+    println!("Blocks without a perfect matching = {}", num_without_matching);
+}
@@ -0,0 +1,91 @@
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::read_edges;
+use xmrtrace::dm::{build_txgraph, dm_decomposition, dm_decomposition_incremental, read_dm_state};
+use xmrtrace::precheck::precheck_ring;
+
+fn main() {
+    let cmd = Command::new("CryptoNote Ring Pre-check")
+    .bin_name("precheck")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Input Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges")
+    )
+    .arg(
+        Arg::new("ring")
+            .value_name("Proposed Ring")
+            .required(true)
+            .value_parser(clap::value_parser!(u32))
+            .use_value_delimiter(true)
+            .multiple_values(true)
+            .long_help("A comma separated list of the public key indices in the proposed ring")
+    )
+    .arg(
+        Arg::new("state_file")
+            .long("state-file")
+            .value_name("DM State Filename")
+            .long_help("The name of a DM state file saved by dmdec for the same edge file. \
+            If given, the DM decomposition of the current graph is not calculated from scratch. If the ring of any \
+            key image in the state differs from the edge file, the state is ignored")
+    )
+    .after_help("The precheck command checks whether adding a proposed ring to the transaction graph would put \
+    its key image in a closed set of the Dulmage-Mendelsohn decomposition, and lists the existing key images \
+    whose rings would shrink after the decomposition. The proposed ring can only contain public keys that \
+    appear in the edge file.");
+
+
+    let m = cmd.get_matches();
+    let edge_file = m.get_one::<String>("input_file").unwrap();
+    let mut ring: Vec<u32> = m.get_many::<u32>("ring").unwrap().copied().collect();
+    let num_indices = ring.len();
+    ring.sort_unstable();
+    ring.dedup();
+
+    let mut start_instant = Instant::now();
+    let (pk_indices, ki_indices, max_pk_index, max_ki_index) = read_edges(edge_file);
+    let mut end_instant = Instant::now();
+    println!("Edge file read in {:?}", end_instant.duration_since(start_instant));
+
+    let num_pks = (max_pk_index+1) as usize;
+    let num_kis = (max_ki_index+1) as usize ;
+    println!("Num keyimages = {}, Num public keys = {}", num_kis, num_pks);
+
+    start_instant = Instant::now();
+    let g = build_txgraph(pk_indices, ki_indices, num_pks, num_kis);
+    let dmd = match m.get_one::<String>("state_file").and_then(|f| read_dm_state(f)) {
+        Some(state) => dm_decomposition_incremental(&g, &state).unwrap_or_else(|| {
+            println!("State is inconsistent with the edge file. Calculating decomposition from scratch");
+            dm_decomposition(&g)
+        }),
+        None => dm_decomposition(&g),
+    };
+    end_instant = Instant::now();
+    println!("DM decomposition of current graph calculated in {:?}", end_instant.duration_since(start_instant));
+
+    start_instant = Instant::now();
+    let check = precheck_ring(&g, &dmd, &ring);
+    end_instant = Instant::now();
+    println!("Ring checked in {:?}", end_instant.duration_since(start_instant));
+
+    println!("Proposed ring size = {}", ring.len());
+    if ring.len() < num_indices {
+        println!("Duplicate public key indices (ignored) = {}", num_indices - ring.len());
+    }
+    if check.ring_consumed {
+        println!("All ring members are already spent by existing closed sets. The ring cannot be spent");
+        return;
+    }
+    println!("Ring size after DM decomposition = {}", check.effective_ring_size);
+    match check.closed_set_size {
+        Some(1) => println!("New keyimage would be traceable (singleton closed set)"),
+        Some(s) => println!("New keyimage would be in a closed set of size {}", s),
+        None => println!("New keyimage would not be in a closed set"),
+    }
+    println!("Number of existing rings that would shrink = {}", check.shrunk_rings.len());
+    for (ki, before, after) in &check.shrunk_rings {
+        println!("Keyimage {}: ring size after DM decomposition {} -> {}", ki, before, after);
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_amounts, read_edges, read_heights};
+use xmrtrace::chains::{chain_arg, chain_profile, check_ring_sizes};
+
+fn main() {
+    let cmd = Command::new("Ring Size Compliance Checker")
+    .bin_name("ringsize_check")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Input Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges")
+    )
+    .arg(
+        Arg::new("ki_heights_file")
+            .value_name("Key Image Spend Heights Filename")
+            .required(true)
+            .long_help("The name of the file containing the block height at which each key image appeared")
+    )
+    .arg(
+        Arg::new("violations_file")
+            .value_name("Violations Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have one row per ring violating the ring size rule of its era")
+    )
+    .arg(
+        chain_arg("The chain whose ring size rules are checked")
+            .default_value("monero")
+    )
+    .arg(
+        Arg::new("ki_amounts_file")
+            .long("ki-amounts")
+            .value_name("Key Image Amounts Filename")
+            .long_help("The name of the file containing the amount of the output spent by each key image. Small \
+            rings are only counted as dust rings if they spend a non-RingCT amount")
+    )
+    .after_help("The ringsize_check command checks the size of every ring against the minimum and maximum ring sizes \
+    enforced by the chain at the height of its key image. Until RingCT was required, inputs spending unmixable dust \
+    amounts could have rings below the minimum. Small rings mined before that height are counted as dust rings \
+    instead of violations, unless the key image amounts show that they spend RingCT outputs. Each row of an amounts \
+    file has an index followed by an amount in atomic units, separated by a space. RingCT outputs have the amount 0. \
+    Other violations point to bugs in the extraction of rings or heights. Each row of the violations file has \
+    the key image index, its height, the ring size and the minimum and maximum ring sizes of the era, separated by \
+    spaces. A maximum of 0 means that the era has no maximum ring size.");
+
+
+    let m = cmd.get_matches();
+    let input_fname = m.get_one::<String>("input_file").unwrap();
+    let ki_heights_fname = m.get_one::<String>("ki_heights_file").unwrap();
+    let violations_fname = m.get_one::<String>("violations_file").unwrap();
+    let profile = chain_profile(m.get_one::<String>("chain").unwrap());
+
+    let start_instant = Instant::now();
+    let (pk_indices, ki_indices, _max_pk_index, max_ki_index) = read_edges(input_fname);
+    let ki_heights = read_heights(ki_heights_fname);
+    let ki_amounts = m.get_one::<String>("ki_amounts_file").map(|f| read_amounts(f));
+    let end_instant = Instant::now();
+    println!("Edge and heights files read in {:?}", end_instant.duration_since(start_instant));
+
+    let num_kis = (max_ki_index+1) as usize;
+    let mut tx_rings: Vec<Vec<u32>> = vec![vec![]; num_kis];
+    for (pk, ki) in pk_indices.iter().zip(ki_indices.iter()) {
+        tx_rings[*ki as usize].push(*pk);
+    }
+    for ring in tx_rings.iter_mut() {
+        ring.sort_unstable();
+        ring.dedup();
+    }
+
+    let (eras, violations, num_unknown_heights) = check_ring_sizes(&tx_rings, &ki_heights, ki_amounts.as_deref(), profile);
+
+    let file_err_msg = "Unable to create or write to file";
+    let violations_file = File::create(violations_fname).expect(file_err_msg);
+    let mut buf = BufWriter::new(violations_file);
+    for (ki, height) in &violations {
+        let rule = profile.ring_size_rule(*height as u64);
+        writeln!(buf, "{} {} {} {} {}",
+            ki,
+            height,
+            tx_rings[*ki as usize].len(),
+            rule.min_ring_size,
+            rule.max_ring_size.unwrap_or(0),
+        ).expect(file_err_msg);
+    }
+
+    println!("Chain = {}", profile.name);
+    for (rule, era) in profile.ring_size_rules.iter().zip(eras.iter()) {
+        let max_ring_size = rule.max_ring_size.map_or("none".to_string(), |max| max.to_string());
+        println!("Era from height {} (min ring size {}, max ring size {}): rings = {}, too small = {}, too large = {}, dust rings below the minimum = {}",
+            rule.from_height,
+            rule.min_ring_size,
+            max_ring_size,
+            era.num_rings,
+            era.too_small,
+            era.too_large,
+            era.dust_small,
+        );
+        if !era.violation_sizes.is_empty() {
+            println!("  Violating ring sizes: {:?}", era.violation_sizes);
+        }
+    }
+    println!("Total violations = {}", violations.len());
+    println!("Rings with unknown heights (not checked) = {}", num_unknown_heights);
+}
@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::{fs::File, io::{BufWriter, Write}};
+use std::path::Path;
+
+use std::time::Instant;
+use clap::{Arg, ArgMatches, Command};
+use rusqlite::Connection;
+
+use xmrtrace::read_lines;
+use xmrtrace::db::{combine_fork_intersections, create_chain_tables, fork_keyimages_table, insert_keyimage, insert_output,
+    keyimage_by_image, keyimages_table, num_rows, open_database, output_by_index, outputs_table, read_keyimages, read_outputs,
+    trim_rings_with_fork, MAIN_CHAIN};
+
+fn main() {
+    let cmd = Command::new("SQLite Key Image and Output Store")
+    .bin_name("sqlite_db")
+    .subcommand_required(true)
+    .arg(
+        Arg::new("database_file")
+            .value_name("Database Filename")
+            .required(true)
+            .long_help("The name of the SQLite database file. It is created if it does not exist")
+    )
+    .arg(
+        Arg::new("chain")
+            .long("chain")
+            .value_name("Chain Name")
+            .default_value(MAIN_CHAIN)
+            .global(true)
+            .long_help("The table prefix of the chain, e.g. xmr, xmv, xmo, xmrv7 or xmrv9")
+    )
+    .subcommand(
+        Command::new("init")
+            .about("Create the keyimage and output tables of the chain")
+    )
+    .subcommand(
+        Command::new("import-rings")
+            .about("Import the rings written by ingest_json or ingest_blobs")
+            .arg(Arg::new("input_file").value_name("Ring Filename").required(true))
+    )
+    .subcommand(
+        Command::new("import-blocks")
+            .about("Import the keyimages, outputs and edges written by ingest_blocks")
+            .arg(Arg::new("input_dir").value_name("Ingestion Directory").required(true))
+    )
+    .subcommand(
+        Command::new("export-edges")
+            .about("Write the edges of the rings of the chain")
+            .arg(Arg::new("output_file").value_name("Output Edge Filename").required(true))
+            .arg(
+                Arg::new("hex")
+                    .long("hex")
+                    .takes_value(false)
+                    .long_help("Write hexadecimal key images and addresses instead of indices")
+            )
+            .arg(
+                Arg::new("fork_indices")
+                    .long("fork-indices")
+                    .takes_value(false)
+                    .long_help("Use the rings trimmed with the forks where they are available")
+            )
+            .arg(
+                Arg::new("heights_file")
+                    .long("heights-file")
+                    .value_name("Key Image Heights Filename")
+                    .long_help("Also write the block height of each key image index")
+            )
+    )
+    .subcommand(
+        Command::new("trim-fork")
+            .about("Trim the rings of the main chain with the rings of a fork")
+            .arg(Arg::new("fork").value_name("Fork Chain Name").required(true))
+    )
+    .subcommand(
+        Command::new("combine-forks")
+            .about("Intersect the trimmed rings of the forks into the main chain")
+            .arg(Arg::new("forks").value_name("Fork Chain Names").required(true).multiple_values(true))
+    )
+    .subcommand(
+        Command::new("keyimage")
+            .about("Print the record of a key image")
+            .arg(Arg::new("image").value_name("Hexadecimal Key Image").required(true))
+    )
+    .subcommand(
+        Command::new("output")
+            .about("Print the output with the given amount and index")
+            .arg(Arg::new("amount").value_name("Amount").required(true).value_parser(clap::value_parser!(u64)))
+            .arg(Arg::new("index").value_name("Global Index").required(true).value_parser(clap::value_parser!(u64)))
+    )
+    .subcommand(
+        Command::new("stats")
+            .about("Print the number of rows of the tables of the chain and its trimmed forks")
+            .arg(Arg::new("forks").value_name("Fork Chain Names").multiple_values(true))
+    )
+    .after_help("The sqlite_db command manages an embedded database with the same tables as the Postgres scripts. \
+    Each chain has a <chain>_keyimages table and a <chain>_outputs table, and the rings of the main chain trimmed with \
+    a fork are in xmr_<fork>_keyimages. Ring indices are stored as JSON arrays. The exported edges use the key image \
+    id minus one as the key image index and the output id minus one as the public key index. Ring members whose \
+    outputs are not in the outputs table are skipped.");
+
+
+    let m = cmd.get_matches();
+    let db_fname = m.get_one::<String>("database_file").unwrap();
+    let mut conn = open_database(db_fname);
+
+    let start_instant = Instant::now();
+    match m.subcommand() {
+        Some(("init", sm)) => {
+            create_chain_tables(&conn, chain(sm));
+        },
+        Some(("import-rings", sm)) => import_rings(&mut conn, chain(sm), sm.get_one::<String>("input_file").unwrap()),
+        Some(("import-blocks", sm)) => import_blocks(&mut conn, chain(sm), Path::new(sm.get_one::<String>("input_dir").unwrap())),
+        Some(("export-edges", sm)) => export_edges(&conn, chain(sm), sm),
+        Some(("trim-fork", sm)) => {
+            let fork = sm.get_one::<String>("fork").unwrap();
+            let summary = trim_rings_with_fork(&mut conn, fork);
+            println!("Keyimages on both chains = {}", summary.shared_keyimages);
+            println!("Ring members with unknown outputs (removed) = {}", summary.unknown_outputs);
+            println!("Keyimages with a different ring amount on the fork = {}", summary.amount_mismatches);
+            print_intersection_histogram(&conn, &fork_keyimages_table(fork));
+        },
+        Some(("combine-forks", sm)) => {
+            let forks: Vec<String> = sm.get_many::<String>("forks").unwrap().cloned().collect();
+            let num_updated = combine_fork_intersections(&mut conn, &forks);
+            println!("Keyimages with fork indices = {}", num_updated);
+            print_intersection_histogram(&conn, &keyimages_table(MAIN_CHAIN));
+        },
+        Some(("keyimage", sm)) => {
+            let image = sm.get_one::<String>("image").unwrap();
+            match keyimage_by_image(&conn, &keyimages_table(chain(sm)), image) {
+                Some(record) => {
+                    println!("image = {}", record.image);
+                    println!("id = {}", record.id);
+                    println!("ring_amount = {}", record.ring_amount);
+                    println!("ring_indices = {:?}", record.ring_indices);
+                    println!("distinct_ring_indices = {:?}", record.distinct_ring_indices);
+                    println!("block_height = {:?}", record.block_height);
+                    println!("fork_indices = {:?}", record.fork_indices);
+                },
+                None => println!("Keyimage not found"),
+            }
+        },
+        Some(("output", sm)) => {
+            let amount = *sm.get_one::<u64>("amount").unwrap();
+            let index = *sm.get_one::<u64>("index").unwrap();
+            match output_by_index(&conn, chain(sm), amount, index) {
+                Some((id, address)) => println!("id = {}, address = {}", id, address),
+                None => println!("Output not found"),
+            }
+        },
+        Some(("stats", sm)) => {
+            let c = chain(sm);
+            let mut tables = vec![keyimages_table(c), outputs_table(c)];
+            if let Some(forks) = sm.get_many::<String>("forks") {
+                tables.extend(forks.map(|f| fork_keyimages_table(f)));
+            }
+            for table in tables {
+                match num_rows(&conn, &table) {
+                    Some(n) => println!("{} rows = {}", table, n),
+                    None => println!("{} does not exist", table),
+                }
+            }
+        },
+        _ => unreachable!(),
+    }
+    let end_instant = Instant::now();
+    println!("Completed in {:?}", end_instant.duration_since(start_instant));
+}
+
+fn chain(
+    sm: &ArgMatches,
+) -> &str {
+    sm.get_one::<String>("chain").unwrap()
+}
+
+// Reads rows of a hexadecimal key image, the ring amount and the global
+// index of a ring member. The rows of a key image are consecutive.
+fn import_rings(
+    conn: &mut Connection,
+    chain: &str,
+    input_fname: &str,
+) {
+    create_chain_tables(conn, chain);
+    let mut rings: Vec<(String, u64, Vec<u64>)> = vec![];
+    if let Ok(lines) = read_lines(input_fname) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 2);
+            let amount = items[1].parse::<u64>().unwrap();
+            let index = items[2].parse::<u64>().unwrap();
+            match rings.last_mut() {
+                Some((image, _, indices)) if image == items[0] => indices.push(index),
+                _ => rings.push((items[0].to_string(), amount, vec![index])),
+            }
+        }
+    }
+
+    let tx = conn.transaction().unwrap();
+    let num_inserted = rings.iter()
+        .filter(|(image, amount, indices)| insert_keyimage(&tx, chain, image, *amount, indices, None))
+        .count();
+    tx.commit().expect("Unable to commit keyimages");
+    println!("Keyimages read = {}, inserted = {}", rings.len(), num_inserted);
+}
+
+// Reads the keyimages.txt, outputs.txt and edges.txt files of ingest_blocks
+fn import_blocks(
+    conn: &mut Connection,
+    chain: &str,
+    input_dir: &Path,
+) {
+    create_chain_tables(conn, chain);
+    let open = |fname: &str| read_lines(input_dir.join(fname)).expect("Unable to open ingestion file");
+
+    let tx = conn.transaction().unwrap();
+    // Amount key and global index of each public key index
+    let mut outputs: HashMap<u32, (u64, u64)> = HashMap::new();
+    let mut num_outputs_inserted = 0_usize;
+    for line in open("outputs.txt").map_while(Result::ok) {
+        let items: Vec<&str> = line.split_whitespace().collect();
+        assert!(items.len() > 5);
+        let pk = items[0].parse::<u32>().unwrap();
+        let amount_key = items[3].parse::<u64>().unwrap();
+        let global_index = items[4].parse::<u64>().unwrap();
+        outputs.insert(pk, (amount_key, global_index));
+        num_outputs_inserted += insert_output(&tx, chain, items[5], amount_key, global_index) as usize;
+    }
+
+    let mut rings: HashMap<u32, Vec<u32>> = HashMap::new();
+    for line in open("edges.txt").map_while(Result::ok) {
+        let items: Vec<u32> = line.split_whitespace().map(|x| x.parse::<u32>().unwrap()).collect();
+        assert_eq!(items.len(), 2);
+        rings.entry(items[0]).or_default().push(items[1]);
+    }
+
+    let mut num_kis_inserted = 0_usize;
+    let mut num_empty_rings = 0_usize;
+    for line in open("keyimages.txt").map_while(Result::ok) {
+        let items: Vec<&str> = line.split_whitespace().collect();
+        assert!(items.len() > 2);
+        let ki = items[0].parse::<u32>().unwrap();
+        let height = items[1].parse::<u64>().unwrap();
+        let Some(ring) = rings.get(&ki) else {
+            num_empty_rings += 1;
+            continue;
+        };
+        let ring_amount = outputs[&ring[0]].0;
+        let ring_indices: Vec<u64> = ring.iter().map(|pk| outputs[pk].1).collect();
+        num_kis_inserted += insert_keyimage(&tx, chain, items[2], ring_amount, &ring_indices, Some(height)) as usize;
+    }
+    tx.commit().expect("Unable to commit ingestion files");
+
+    println!("Outputs inserted = {}", num_outputs_inserted);
+    println!("Keyimages inserted = {}", num_kis_inserted);
+    println!("Keyimages without known ring members (skipped) = {}", num_empty_rings);
+}
+
+fn export_edges(
+    conn: &Connection,
+    chain: &str,
+    sm: &ArgMatches,
+) {
+    let hex = sm.contains_id("hex");
+    let use_fork_indices = sm.contains_id("fork_indices");
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(sm.get_one::<String>("output_file").unwrap()).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+    let mut heights_buf = sm.get_one::<String>("heights_file")
+        .map(|f| BufWriter::new(File::create(f).expect(file_err_msg)));
+
+    let outputs = read_outputs(conn, chain);
+    let mut num_edges = 0_usize;
+    let mut num_unknown = 0_usize;
+    let mut num_trimmed = 0_usize;
+
+    let records = read_keyimages(conn, &keyimages_table(chain));
+    for record in &records {
+        let ki = record.id - 1;
+        let ring = match &record.fork_indices {
+            Some(fork_indices) if use_fork_indices => {
+                num_trimmed += 1;
+                fork_indices
+            },
+            _ => &record.distinct_ring_indices,
+        };
+        for index in ring {
+            let Some((pk_id, address)) = outputs.get(&(record.ring_amount, *index)) else {
+                num_unknown += 1;
+                continue;
+            };
+            if hex {
+                writeln!(buf, "{} {}", record.image, address).expect(file_err_msg);
+            }
+            else {
+                writeln!(buf, "{} {}", ki, pk_id - 1).expect(file_err_msg);
+            }
+            num_edges += 1;
+        }
+        if let (Some(hbuf), Some(height)) = (heights_buf.as_mut(), record.block_height) {
+            writeln!(hbuf, "{} {}", ki, height).expect(file_err_msg);
+        }
+    }
+
+    println!("Keyimages = {}, edges = {}", records.len(), num_edges);
+    if use_fork_indices {
+        println!("Keyimages with rings trimmed by forks = {}", num_trimmed);
+    }
+    println!("Ring members with unknown outputs (skipped) = {}", num_unknown);
+}
+
+fn print_intersection_histogram(
+    conn: &Connection,
+    table: &str,
+) {
+    let mut hist: Vec<usize> = vec![];
+    for record in read_keyimages(conn, table) {
+        if let Some(fork_indices) = record.fork_indices {
+            if hist.len() <= fork_indices.len() {
+                hist.resize(fork_indices.len() + 1, 0);
+            }
+            hist[fork_indices.len()] += 1;
+        }
+    }
+    for (size, count) in hist.iter().enumerate() {
+        if *count != 0 {
+            println!("{} {}", size, count);
+        }
+    }
+    println!("Total number of images = {}", hist.iter().sum::<usize>());
+    println!("Intersection size 1 = {}", hist.get(1).copied().unwrap_or(0));
+    println!("Rest = {}", hist.iter().skip(2).sum::<usize>());
+}
@@ -1,7 +1,11 @@
 use std::time::Instant;
 use clap::{Arg, Command};
 
+use std::collections::HashMap;
+
 use xmrtrace::{read_edges, read_rings};
+use xmrtrace::entropy::{ring_entropies, entropy_histogram, ENTROPY_BIN_WIDTH};
+use xmrtrace::chains::{histogram_chain_arg, histogram_ring_size};
 
 fn main() {
     let cmd = Command::new("CryptoNote Clustering Algorithm Statistics")
@@ -24,6 +28,7 @@ fn main() {
             .required(true)
             .long_help("The name of the file containing a list of rings after the clustering algorithm")
     )
+    .arg(histogram_chain_arg())
     .after_help("The stats_cla command calculates statistics after the cascade and clustering algorithms attacks on a set of CryptoNote transactions. \
     The transaction graph is described in a file. Each row in the file describes an edge. The first two entries in each row are non-negative integers separated by a space. The first integer is a key image identifier and the second integer is the public key identifier. The identifier spaces can overlap.");
 
@@ -53,10 +58,13 @@ fn main() {
        tx_rings[ki_indices[i] as usize].push(pk_indices[i]); 
     }
 
-    let max_ring_size = 10_usize;
+    let max_ring_size = histogram_ring_size(&m);
     let mut initial_mixin_histogram = vec![0_u32; max_ring_size+1];
     for ki in 0..num_kis {
         let l = tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
         
         if l <= max_ring_size {
             initial_mixin_histogram[l-1] += 1;
@@ -76,6 +84,9 @@ fn main() {
     let mut cascade_traceable_ring_mixin_histogram = vec![0_u32; max_ring_size+1];
     for ki in 0..num_kis {
         let l = post_cascade_tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
         
         if l <= max_ring_size {
             post_cascade_mixin_histogram[l-1] += 1;
@@ -117,6 +128,9 @@ fn main() {
     let mut cluster_traceable_ring_mixin_histogram = vec![0_u32; max_ring_size+1];
     for ki in 0..num_kis {
         let l = post_cluster_tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
         
         if l <= max_ring_size {
             post_cluster_mixin_histogram[l-1] += 1;
@@ -149,4 +163,14 @@ fn main() {
         num_rings_traced_by_clustering_algo += diff;
     }
     println!("Total number of rings traced by clustering algorithm = {}", num_rings_traced_by_clustering_algo);
+
+    // The spend distributions are uniform over the remaining ring members,
+    // so the Shannon entropy and min-entropy are equal
+    println!("Entropy histograms have bins of width {} bits", ENTROPY_BIN_WIDTH);
+    println!("Initial entropy histogram:\n {:?}",
+        entropy_histogram(ring_entropies(&tx_rings, &HashMap::new()).iter().map(|e| e.0)));
+    println!("Post cascade attack entropy histogram:\n {:?}",
+        entropy_histogram(ring_entropies(&post_cascade_tx_rings, &HashMap::new()).iter().map(|e| e.0)));
+    println!("Post clustering algorithm entropy histogram:\n {:?}",
+        entropy_histogram(ring_entropies(&post_cluster_tx_rings, &HashMap::new()).iter().map(|e| e.0)));
 }
\ No newline at end of file
@@ -1,7 +1,11 @@
 use std::time::Instant;
 use clap::{Arg, Command};
 
-use xmrtrace::{read_edges, read_rings};
+use std::collections::HashMap;
+
+use xmrtrace::{read_edges, read_rings, read_edge_probabilities};
+use xmrtrace::entropy::{ring_entropies, entropy_histogram, group_probabilities_by_keyimage, ENTROPY_BIN_WIDTH};
+use xmrtrace::chains::{histogram_chain_arg, histogram_ring_size};
 
 fn main() {
     let cmd = Command::new("DM Decomposition Statistics")
@@ -24,6 +28,15 @@ fn main() {
             .required(true)
             .long_help("The name of the file containing a list of rings after the DM decomposition")
     )
+    .arg(
+        Arg::new("probability_file")
+            .long("probabilities")
+            .value_name("Spend Probabilities Filename")
+            .long_help("The name of a file written by the marginals command. If given, the post DM decomposition \
+            entropies of the key images in the file are calculated from these probabilities instead of assuming \
+            that all remaining ring members are equally likely")
+    )
+    .arg(histogram_chain_arg())
     .after_help("The stats_dm command calculates statistics after the Dulmage-Mendelsohn decomposition on a set of CryptoNote transactions.");
 
 
@@ -47,10 +60,13 @@ fn main() {
     end_instant = Instant::now();
     println!("Pre DM decomposition rings file read in {:?}", end_instant.duration_since(start_instant));
 
-    let max_ring_size = 10_usize;
+    let max_ring_size = histogram_ring_size(&m);
     let mut pre_dm_mixin_histogram = vec![0_u32; max_ring_size+1];
     for ki in 0..num_kis {
         let l = pre_dmd_tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
         
         if l <= max_ring_size {
             pre_dm_mixin_histogram[l-1] += 1;
@@ -70,6 +86,9 @@ fn main() {
     let mut dm_traceable_ring_mixin_histogram = vec![0_u32; max_ring_size+1];
     for ki in 0..num_kis {
         let l = post_dmd_tx_rings[ki].len();
+        if l == 0 {
+            continue; // Key image outside the analyzed height range
+        }
         
         if l <= max_ring_size {
             post_dm_mixin_histogram[l-1] += 1;
@@ -91,4 +110,19 @@ fn main() {
     println!("Post DM decomposition mixin histogram:\n {:?}", post_dm_mixin_histogram);
     println!("DM decomposition traceable ring mixin histogram:\n {:?}", dm_traceable_ring_mixin_histogram);
 
+    let post_dm_probs = match m.get_one::<String>("probability_file") {
+        Some(f) => group_probabilities_by_keyimage(&read_edge_probabilities(f)),
+        None => HashMap::new(),
+    };
+    let pre_dm_entropies = ring_entropies(&pre_dmd_tx_rings, &HashMap::new());
+    let post_dm_entropies = ring_entropies(&post_dmd_tx_rings, &post_dm_probs);
+
+    println!("Entropy histograms have bins of width {} bits", ENTROPY_BIN_WIDTH);
+    println!("Pre DM decomposition entropy histogram:\n {:?}",
+        entropy_histogram(pre_dm_entropies.iter().map(|e| e.0)));
+    println!("Post DM decomposition Shannon entropy histogram:\n {:?}",
+        entropy_histogram(post_dm_entropies.iter().map(|e| e.0)));
+    println!("Post DM decomposition min-entropy histogram:\n {:?}",
+        entropy_histogram(post_dm_entropies.iter().map(|e| e.1)));
+
 }
\ No newline at end of file
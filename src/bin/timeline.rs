@@ -0,0 +1,143 @@
+use std::{fs::File, io::{BufWriter, Write}};
+
+use std::time::Instant;
+use std::collections::BTreeMap;
+use clap::{Arg, Command};
+
+use xmrtrace::{read_edges, read_heights};
+use xmrtrace::dm::{build_txgraph, dm_decomposition};
+use xmrtrace::temporal::filter_edges_by_height;
+
+fn main() {
+    let cmd = Command::new("Dulmage-Mendelsohn Decomposition Timeline")
+    .bin_name("timeline")
+    .arg(
+        Arg::new("input_file")
+            .value_name("Input Edge Filename")
+            .required(true)
+            .long_help("The name of the input file containing a list of edges")
+    )
+    .arg(
+        Arg::new("ki_heights_file")
+            .value_name("Key Image Spend Heights Filename")
+            .required(true)
+            .long_help("The name of the file containing the block height at which each key image appeared")
+    )
+    .arg(
+        Arg::new("timeline_file")
+            .value_name("Timeline Output Filename")
+            .required(true)
+            .long_help("The name of the output file that will have one line of statistics per analyzed height")
+    )
+    .arg(
+        Arg::new("heights")
+            .long("heights")
+            .value_name("Heights")
+            .value_parser(clap::value_parser!(u32))
+            .use_value_delimiter(true)
+            .multiple_values(true)
+            .conflicts_with("step")
+            .long_help("A comma separated list of block heights at which the DM decomposition is calculated")
+    )
+    .arg(
+        Arg::new("step")
+            .long("step")
+            .value_name("Step")
+            .value_parser(clap::value_parser!(u32))
+            .long_help("The number of blocks between consecutive heights at which the DM decomposition is calculated")
+    )
+    .arg(
+        Arg::new("from_height")
+            .long("from-height")
+            .value_name("From Height")
+            .value_parser(clap::value_parser!(u32))
+            .long_help("Only key images that appeared at or after this block height are analyzed. \
+            It is also the first height of the timeline when --step is used")
+    )
+    .arg(
+        Arg::new("to_height")
+            .long("to-height")
+            .value_name("To Height")
+            .value_parser(clap::value_parser!(u32))
+            .long_help("The last height of the timeline when --step is used. \
+            Defaults to the largest key image height")
+    )
+    .after_help("The timeline command calculates the Dulmage-Mendelsohn decomposition of the transaction graph \
+    restricted to the key images that appeared up to each height in a series of block heights. \
+    Each row of the output file has a block height, the number of key images up to that height, \
+    the number of traced key images (singleton closed sets) and the number of closed sets, \
+    followed by the closed set size histogram as space separated size:count pairs.");
+
+
+    let m = cmd.get_matches();
+    let edge_file = m.get_one::<String>("input_file").unwrap();
+    let ki_heights_file = m.get_one::<String>("ki_heights_file").unwrap();
+    let timeline_file = m.get_one::<String>("timeline_file").unwrap();
+
+    let start_instant = Instant::now();
+    let (pk_indices, ki_indices, max_pk_index, max_ki_index) = read_edges(edge_file);
+    let ki_heights = read_heights(ki_heights_file);
+    let end_instant = Instant::now();
+    println!("Edge and heights files read in {:?}", end_instant.duration_since(start_instant));
+
+    let num_pks = (max_pk_index+1) as usize;
+    let num_kis = (max_ki_index+1) as usize ;
+    println!("Num keyimages = {}, Num public keys = {}", num_kis, num_pks);
+
+    let from_height = *m.get_one::<u32>("from_height").unwrap_or(&0);
+    let heights: Vec<u32> = if let Some(h) = m.get_many::<u32>("heights") {
+        h.copied().collect()
+    }
+    else {
+        let max_ki_height = ki_heights.iter().filter(|h| **h != u32::MAX).max().copied().unwrap_or(0);
+        let to_height = *m.get_one::<u32>("to_height").unwrap_or(&max_ki_height);
+        let step = *m.get_one::<u32>("step").expect("Either --heights or --step is required");
+        assert!(step > 0);
+        let mut hv: Vec<u32> = (from_height..=to_height).step_by(step as usize).collect();
+        if hv.last() != Some(&to_height) {
+            hv.push(to_height);
+        }
+        hv
+    };
+
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(timeline_file).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    for height in heights {
+        let start_instant = Instant::now();
+        let mut window_pk_indices = pk_indices.clone();
+        let mut window_ki_indices = ki_indices.clone();
+        filter_edges_by_height(&mut window_pk_indices, &mut window_ki_indices, &ki_heights, from_height, height);
+
+        let mut ki_in_window = vec![false; num_kis];
+        for ki in &window_ki_indices {
+            ki_in_window[*ki as usize] = true;
+        }
+        let num_window_kis = ki_in_window.iter().filter(|w| **w).count();
+
+        let g = build_txgraph(window_pk_indices, window_ki_indices, num_pks, num_kis);
+        let dmd = dm_decomposition(&g);
+
+        let mut closed_set_size_histogram: BTreeMap<usize, u32> = BTreeMap::new();
+        for block in &dmd.fine_blocks {
+            closed_set_size_histogram.entry(block.len()).and_modify(|c| *c += 1).or_insert(1);
+        }
+        let num_traced = dmd.num_singletons();
+        let end_instant = Instant::now();
+
+        println!("Height {}: {} traced out of {} keyimages, {} closed sets. Time taken = {:?}.",
+            height,
+            num_traced,
+            num_window_kis,
+            dmd.fine_blocks.len(),
+            end_instant.duration_since(start_instant),
+        );
+
+        write!(buf, "{} {} {} {}", height, num_window_kis, num_traced, dmd.fine_blocks.len()).expect(file_err_msg);
+        for (size, count) in &closed_set_size_histogram {
+            write!(buf, " {}:{}", size, count).expect(file_err_msg);
+        }
+        writeln!(buf).expect(file_err_msg);
+    }
+}
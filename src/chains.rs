@@ -0,0 +1,282 @@
+// Consensus parameters of CryptoNote chains.
+//
+// The analyses only need the rules that shape the rings: the minimum and
+// maximum ring sizes in force at each height and the number of blocks after
+// which regular and coinbase outputs can be spent. Chains forked from Monero share its history
+// up to the fork height and keep the ring size rules in force at the fork.
+
+use std::collections::BTreeMap;
+
+use clap::{Arg, ArgMatches, builder::PossibleValuesParser};
+
+use crate::temporal::{COINBASE_UNLOCK_WINDOW, DEFAULT_SPENDABLE_AGE};
+
+// Largest ring size with its own bin in the ring size histograms when the
+// ring size is not bounded
+pub const DEFAULT_LARGEST_RING_SIZE: usize = 10;
+
+// Ring size rule in force from a height until the next rule
+pub struct RingSizeRule {
+    pub from_height: u64,
+    pub min_ring_size: usize,
+    // None if rings of any size above the minimum are accepted
+    pub max_ring_size: Option<usize>,
+}
+
+pub struct ChainProfile {
+    pub name: &'static str,
+    pub spendable_age: u32,
+    pub coinbase_unlock_window: u32,
+    // Height from which every input must spend RingCT outputs. Before it,
+    // inputs spending unmixable (dust) amounts could have rings smaller than
+    // the minimum. None if the chain never required RingCT.
+    pub ringct_required_height: Option<u64>,
+    // Rules in increasing order of height, starting at height 0
+    pub ring_size_rules: &'static [RingSizeRule],
+}
+
+// Compliance of the rings of one era with its ring size rule
+#[derive(Default)]
+pub struct EraCompliance {
+    pub num_rings: usize,
+    pub too_small: usize,
+    pub too_large: usize,
+    // Rings below the minimum that may spend unmixable dust amounts
+    pub dust_small: usize,
+    // Number of violating rings of each size
+    pub violation_sizes: BTreeMap<usize, usize>,
+}
+
+impl ChainProfile {
+    pub fn ring_size_rule(&self, height: u64) -> &RingSizeRule {
+        &self.ring_size_rules[self.era(height)]
+    }
+
+    // Index of the ring size rule in force at a height
+    pub fn era(&self, height: u64) -> usize {
+        self.ring_size_rules.iter()
+            .rposition(|rule| rule.from_height <= height)
+            .expect("Ring size rules must start at height 0")
+    }
+
+    // Rings spending non-RingCT amounts were exempt from the minimum ring
+    // size when there were not enough outputs of their amount to mix with
+    pub fn dust_exception_applies(&self, height: u64) -> bool {
+        self.ringct_required_height.is_none_or(|h| height < h)
+    }
+
+    pub fn ring_size_is_valid(&self, height: u64, ring_size: usize) -> bool {
+        let rule = self.ring_size_rule(height);
+        ring_size >= rule.min_ring_size && rule.max_ring_size.is_none_or(|max| ring_size <= max)
+    }
+
+    // Largest ring size fixed or required by any rule, used to size ring
+    // size histograms. Rings of any size above the minimum are accepted if
+    // the last rule has no maximum, so the default histogram size is kept.
+    pub fn largest_ring_size(&self) -> usize {
+        let largest = self.ring_size_rules.iter()
+            .map(|rule| rule.max_ring_size.unwrap_or(rule.min_ring_size))
+            .max()
+            .unwrap_or(1);
+        match self.ring_size_rules.last() {
+            Some(rule) if rule.max_ring_size.is_none() => largest.max(DEFAULT_LARGEST_RING_SIZE),
+            _ => largest,
+        }
+    }
+}
+
+// Checks the size of every non-empty ring against the rule in force at the
+// height of its key image. Heights of u32::MAX are unknown. Rings below the
+// minimum that were mined before RingCT was required and do not spend a
+// RingCT amount (0) are counted as dust rings instead of violations. Without
+// key image amounts, or for amounts of u64::MAX, every such ring is counted
+// as a dust ring. Returns the compliance of each era, the (key image, height)
+// pairs of the violating rings and the number of rings with unknown heights.
+pub fn check_ring_sizes(
+    tx_rings: &[Vec<u32>],
+    ki_heights: &[u32],
+    ki_amounts: Option<&[u64]>,
+    profile: &ChainProfile,
+) -> (Vec<EraCompliance>, Vec<(u32, u32)>, usize) {
+    let mut eras: Vec<EraCompliance> = profile.ring_size_rules.iter().map(|_| EraCompliance::default()).collect();
+    let mut violations: Vec<(u32, u32)> = vec![];
+    let mut num_unknown_heights = 0_usize;
+
+    for (ki, ring) in tx_rings.iter().enumerate() {
+        if ring.is_empty() {
+            continue;
+        }
+        let height = ki_heights.get(ki).copied().unwrap_or(u32::MAX);
+        if height == u32::MAX {
+            num_unknown_heights += 1;
+            continue;
+        }
+        let era_index = profile.era(height as u64);
+        let rule = &profile.ring_size_rules[era_index];
+        let era = &mut eras[era_index];
+        era.num_rings += 1;
+
+        let too_small = ring.len() < rule.min_ring_size;
+        let too_large = rule.max_ring_size.is_some_and(|max| ring.len() > max);
+        let amount = ki_amounts.map_or(u64::MAX, |amounts| amounts.get(ki).copied().unwrap_or(u64::MAX));
+        if too_small && amount != 0 && profile.dust_exception_applies(height as u64) {
+            era.dust_small += 1;
+        }
+        else if too_small || too_large {
+            era.too_small += too_small as usize;
+            era.too_large += too_large as usize;
+            *era.violation_sizes.entry(ring.len()).or_insert(0) += 1;
+            violations.push((ki as u32, height));
+        }
+    }
+    (eras, violations, num_unknown_heights)
+}
+
+const fn rule(from_height: u64, min_ring_size: usize, max_ring_size: Option<usize>) -> RingSizeRule {
+    RingSizeRule { from_height, min_ring_size, max_ring_size }
+}
+
+// Monero: mixin 2 from v2, mixin 4 from v6, mixin 6 from v7 and fixed ring
+// sizes of 11 from v8 and 16 from v15
+const MONERO_RULES: [RingSizeRule; 6] = [
+    rule(0, 1, None),
+    rule(1009827, 3, None),
+    rule(1400000, 5, None),
+    rule(1546000, 7, None),
+    rule(1685555, 11, Some(11)),
+    rule(2688888, 16, Some(16)),
+];
+
+// RingCT outputs appear from v4 and are required from v6
+const MONERO_RINGCT_REQUIRED_HEIGHT: u64 = 1400000;
+
+// Monero Original forked at the v7 height without adopting its rules
+const MONERO_ORIGINAL_RULES: [RingSizeRule; 3] = [
+    rule(0, 1, None),
+    rule(1009827, 3, None),
+    rule(1400000, 5, None),
+];
+
+// MoneroV forked at height 1564965 and Monero Classic at the v8 height,
+// both keeping the minimum ring size of 7 without a maximum
+const MONERO_V7_RULES: [RingSizeRule; 4] = [
+    rule(0, 1, None),
+    rule(1009827, 3, None),
+    rule(1400000, 5, None),
+    rule(1546000, 7, None),
+];
+
+// Monero 0 forked at the v9 height with the fixed ring size of 11
+const MONERO_V9_RULES: [RingSizeRule; 5] = [
+    rule(0, 1, None),
+    rule(1009827, 3, None),
+    rule(1400000, 5, None),
+    rule(1546000, 7, None),
+    rule(1685555, 11, Some(11)),
+];
+
+const ANY_RING_SIZE_RULES: [RingSizeRule; 1] = [
+    rule(0, 1, None),
+];
+
+const WOWNERO_RULES: [RingSizeRule; 1] = [
+    rule(0, 22, Some(22)),
+];
+
+pub const CHAIN_PROFILES: [ChainProfile; 8] = [
+    ChainProfile {
+        name: "monero",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(MONERO_RINGCT_REQUIRED_HEIGHT),
+        ring_size_rules: &MONERO_RULES,
+    },
+    ChainProfile {
+        name: "monero-original",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(MONERO_RINGCT_REQUIRED_HEIGHT),
+        ring_size_rules: &MONERO_ORIGINAL_RULES,
+    },
+    ChainProfile {
+        name: "monerov",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(MONERO_RINGCT_REQUIRED_HEIGHT),
+        ring_size_rules: &MONERO_V7_RULES,
+    },
+    ChainProfile {
+        name: "monero-v7",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(MONERO_RINGCT_REQUIRED_HEIGHT),
+        ring_size_rules: &MONERO_V7_RULES,
+    },
+    ChainProfile {
+        name: "monero-v9",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(MONERO_RINGCT_REQUIRED_HEIGHT),
+        ring_size_rules: &MONERO_V9_RULES,
+    },
+    ChainProfile {
+        name: "bytecoin",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: 10,
+        ringct_required_height: None,
+        ring_size_rules: &ANY_RING_SIZE_RULES,
+    },
+    ChainProfile {
+        name: "aeon",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: None,
+        ring_size_rules: &ANY_RING_SIZE_RULES,
+    },
+    ChainProfile {
+        name: "wownero",
+        spendable_age: DEFAULT_SPENDABLE_AGE,
+        coinbase_unlock_window: COINBASE_UNLOCK_WINDOW,
+        ringct_required_height: Some(0),
+        ring_size_rules: &WOWNERO_RULES,
+    },
+];
+
+pub fn chain_profile(
+    name: &str,
+) -> &'static ChainProfile {
+    CHAIN_PROFILES.iter()
+        .find(|p| p.name == name)
+        .unwrap_or_else(|| panic!("Unknown chain {}", name))
+}
+
+pub fn chain_names() -> Vec<&'static str> {
+    CHAIN_PROFILES.iter().map(|p| p.name).collect()
+}
+
+// The --chain argument of the commands that use a chain profile. Commands
+// that always need a profile add a default value.
+pub fn chain_arg(
+    long_help: &'static str,
+) -> Arg<'static> {
+    Arg::new("chain")
+        .long("chain")
+        .value_name("Chain Name")
+        .value_parser(PossibleValuesParser::new(chain_names()))
+        .long_help(long_help)
+}
+
+// The --chain argument of the commands that write ring size histograms
+pub fn histogram_chain_arg() -> Arg<'static> {
+    chain_arg("The chain whose largest ring size is the largest ring size with its own bin in the mixin histograms. \
+    Chains without a maximum ring size keep a bin for every ring size up to 10. By default, the rings of size 11 and \
+    larger share the last bin")
+}
+
+// Largest ring size with its own histogram bin, given the matches of a
+// command with histogram_chain_arg
+pub fn histogram_ring_size(
+    m: &ArgMatches,
+) -> usize {
+    m.get_one::<String>("chain").map_or(DEFAULT_LARGEST_RING_SIZE, |c| chain_profile(c).largest_ring_size())
+}
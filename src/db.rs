@@ -0,0 +1,402 @@
+// SQLite store of key images and outputs.
+//
+// The tables have the same layout as the Postgres tables created by the
+// scripts. Each chain has a <chain>_keyimages table and a <chain>_outputs
+// table, e.g. xmr_keyimages and xmv_outputs. The rings trimmed with a fork
+// are kept in xmr_<fork>_keyimages, whose fork_indices column has the
+// ring members that also appear in the ring of the same key image on the
+// fork. SQLite has no array type, so the ring index columns hold JSON
+// arrays of integers, which can be queried with json_each.
+
+use std::collections::{HashMap, HashSet};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+pub const MAIN_CHAIN: &str = "xmr";
+
+pub struct KeyimageRecord {
+    pub image: String,
+    pub id: i64,
+    pub ring_amount: u64,
+    pub ring_indices: Vec<u64>,
+    pub distinct_ring_indices: Vec<u64>,
+    pub block_height: Option<u64>,
+    pub fork_indices: Option<Vec<u64>>,
+}
+
+// Statistics of trimming the rings of the main chain with a fork
+#[derive(Default)]
+pub struct ForkTrimSummary {
+    pub shared_keyimages: usize,
+    pub unknown_outputs: usize,
+    pub amount_mismatches: usize,
+}
+
+pub fn keyimages_table(chain: &str) -> String {
+    format!("{}_keyimages", checked_name(chain))
+}
+
+pub fn outputs_table(chain: &str) -> String {
+    format!("{}_outputs", checked_name(chain))
+}
+
+pub fn fork_keyimages_table(fork: &str) -> String {
+    format!("{}_{}_keyimages", MAIN_CHAIN, checked_name(fork))
+}
+
+// Table names cannot be bound as parameters, so chain names are restricted
+// to characters that are safe to interpolate
+fn checked_name(name: &str) -> &str {
+    assert!(!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()),
+        "Chain names can only have ASCII letters and digits");
+    name
+}
+
+pub fn open_database(
+    filename: &str,
+) -> Connection {
+    let conn = Connection::open(filename).expect("Unable to open database");
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL;").expect("Unable to configure database");
+    conn
+}
+
+fn create_keyimages_table(
+    conn: &Connection,
+    table: &str,
+) {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {}
+        (
+          image                   VARCHAR(64) NOT NULL,
+          id                      INTEGER PRIMARY KEY,
+          ring_amount             BIGINT,
+          ring_indices            TEXT,
+          distinct_ring_indices   TEXT,
+          block_height            INTEGER,
+          fork_indices            TEXT,
+          UNIQUE(image)
+        );",
+        table,
+    )).expect("Unable to create keyimages table");
+}
+
+// Creates the key image and output tables of a chain if they do not exist
+pub fn create_chain_tables(
+    conn: &Connection,
+    chain: &str,
+) {
+    create_keyimages_table(conn, &keyimages_table(chain));
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {}
+        (
+          address       VARCHAR(64),
+          id            INTEGER PRIMARY KEY,
+          amount        BIGINT,
+          \"index\"       INTEGER,
+          UNIQUE(amount, \"index\")
+        );",
+        outputs_table(chain),
+    )).expect("Unable to create outputs table");
+}
+
+fn indices_to_json(indices: &[u64]) -> String {
+    serde_json::to_string(indices).unwrap()
+}
+
+fn indices_from_json(json: &str) -> Vec<u64> {
+    serde_json::from_str(json).expect("Invalid ring indices in database")
+}
+
+// Inserts a key image unless it is already present. Returns whether it
+// was inserted.
+pub fn insert_keyimage(
+    conn: &Connection,
+    chain: &str,
+    image: &str,
+    ring_amount: u64,
+    ring_indices: &[u64],
+    block_height: Option<u64>,
+) -> bool {
+    let mut distinct_ring_indices = ring_indices.to_vec();
+    distinct_ring_indices.sort_unstable();
+    distinct_ring_indices.dedup();
+
+    let mut stmt = conn.prepare_cached(&format!(
+        "INSERT INTO {} (image, ring_amount, ring_indices, distinct_ring_indices, block_height)
+        VALUES (?1, ?2, ?3, ?4, ?5) ON CONFLICT(image) DO NOTHING",
+        keyimages_table(chain),
+    )).unwrap();
+    let num_inserted = stmt.execute(params![
+        image,
+        ring_amount as i64,
+        indices_to_json(ring_indices),
+        indices_to_json(&distinct_ring_indices),
+        block_height.map(|h| h as i64),
+    ]).expect("Unable to insert keyimage");
+    num_inserted > 0
+}
+
+// Inserts an output unless its amount and index are already present.
+// Returns whether it was inserted.
+pub fn insert_output(
+    conn: &Connection,
+    chain: &str,
+    address: &str,
+    amount: u64,
+    index: u64,
+) -> bool {
+    let mut stmt = conn.prepare_cached(&format!(
+        "INSERT INTO {} (address, amount, \"index\") VALUES (?1, ?2, ?3) ON CONFLICT(amount, \"index\") DO NOTHING",
+        outputs_table(chain),
+    )).unwrap();
+    let num_inserted = stmt.execute(params![address, amount as i64, index as i64]).expect("Unable to insert output");
+    num_inserted > 0
+}
+
+fn keyimage_from_row(row: &Row) -> rusqlite::Result<KeyimageRecord> {
+    let ring_indices: String = row.get(3)?;
+    let distinct_ring_indices: String = row.get(4)?;
+    let fork_indices: Option<String> = row.get(6)?;
+    Ok(KeyimageRecord {
+        image: row.get(0)?,
+        id: row.get(1)?,
+        ring_amount: row.get::<_, i64>(2)? as u64,
+        ring_indices: indices_from_json(&ring_indices),
+        distinct_ring_indices: indices_from_json(&distinct_ring_indices),
+        block_height: row.get::<_, Option<i64>>(5)?.map(|h| h as u64),
+        fork_indices: fork_indices.map(|f| indices_from_json(&f)),
+    })
+}
+
+const KEYIMAGE_COLUMNS: &str = "image, id, ring_amount, ring_indices, distinct_ring_indices, block_height, fork_indices";
+
+// Returns the records of a key image table in id order
+pub fn read_keyimages(
+    conn: &Connection,
+    table: &str,
+) -> Vec<KeyimageRecord> {
+    let mut stmt = conn.prepare(&format!("SELECT {} FROM {} ORDER BY id", KEYIMAGE_COLUMNS, table)).unwrap();
+    stmt.query_map([], keyimage_from_row)
+        .expect("Unable to read keyimages")
+        .map(|r| r.expect("Invalid keyimage record"))
+        .collect()
+}
+
+pub fn keyimage_by_image(
+    conn: &Connection,
+    table: &str,
+    image: &str,
+) -> Option<KeyimageRecord> {
+    conn.query_row(
+        &format!("SELECT {} FROM {} WHERE image = ?1", KEYIMAGE_COLUMNS, table),
+        params![image],
+        keyimage_from_row,
+    ).optional().expect("Unable to query keyimage")
+}
+
+// Returns the id and address of the output with the given amount and index
+pub fn output_by_index(
+    conn: &Connection,
+    chain: &str,
+    amount: u64,
+    index: u64,
+) -> Option<(i64, String)> {
+    conn.query_row(
+        &format!("SELECT id, address FROM {} WHERE amount = ?1 AND \"index\" = ?2", outputs_table(chain)),
+        params![amount as i64, index as i64],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    ).optional().expect("Unable to query output")
+}
+
+// Returns the id and address of every output keyed by amount and index
+pub fn read_outputs(
+    conn: &Connection,
+    chain: &str,
+) -> HashMap<(u64, u64), (i64, String)> {
+    let mut stmt = conn.prepare(&format!("SELECT amount, \"index\", id, address FROM {}", outputs_table(chain))).unwrap();
+    stmt.query_map([], |row| {
+            let amount = row.get::<_, i64>(0)? as u64;
+            let index = row.get::<_, i64>(1)? as u64;
+            Ok(((amount, index), (row.get(2)?, row.get(3)?)))
+        })
+        .expect("Unable to read outputs")
+        .map(|r| r.expect("Invalid output record"))
+        .collect()
+}
+
+pub fn num_rows(
+    conn: &Connection,
+    table: &str,
+) -> Option<u64> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0))
+        .ok()
+        .map(|n| n as u64)
+}
+
+// Trims the rings of the main chain with the rings of the same key images
+// on a fork. The key images present on both chains are copied into the
+// xmr_<fork>_keyimages table, where fork_indices has the main chain
+// indices of the ring members whose addresses are in both rings.
+pub fn trim_rings_with_fork(
+    conn: &mut Connection,
+    fork: &str,
+) -> ForkTrimSummary {
+    let mut summary = ForkTrimSummary::default();
+    let fork_table = fork_keyimages_table(fork);
+    create_keyimages_table(conn, &fork_table);
+
+    let main_outputs = read_outputs(conn, MAIN_CHAIN);
+    let fork_outputs = read_outputs(conn, fork);
+    let fork_keyimages: HashMap<String, KeyimageRecord> = read_keyimages(conn, &keyimages_table(fork))
+        .into_iter()
+        .map(|r| (r.image.clone(), r))
+        .collect();
+
+    let tx = conn.transaction().unwrap();
+    {
+        let mut stmt = tx.prepare(&format!(
+            "INSERT INTO {} (image, id, ring_amount, ring_indices, distinct_ring_indices, block_height, fork_indices)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ON CONFLICT(image) DO UPDATE SET fork_indices = excluded.fork_indices",
+            fork_table,
+        )).unwrap();
+
+        for record in read_keyimages(&tx, &keyimages_table(MAIN_CHAIN)) {
+            let Some(fork_record) = fork_keyimages.get(&record.image) else {
+                continue;
+            };
+            summary.shared_keyimages += 1;
+            if fork_record.ring_amount != record.ring_amount {
+                summary.amount_mismatches += 1;
+            }
+
+            let fork_addresses: HashSet<&str> = fork_record.distinct_ring_indices.iter()
+                .filter_map(|i| fork_outputs.get(&(fork_record.ring_amount, *i)).map(|(_, a)| a.as_str()))
+                .collect();
+            let mut fork_indices: Vec<u64> = vec![];
+            for index in &record.distinct_ring_indices {
+                match main_outputs.get(&(record.ring_amount, *index)) {
+                    Some((_, address)) => {
+                        if fork_addresses.contains(address.as_str()) {
+                            fork_indices.push(*index);
+                        }
+                    },
+                    None => summary.unknown_outputs += 1,
+                }
+            }
+
+            stmt.execute(params![
+                record.image,
+                record.id,
+                record.ring_amount as i64,
+                indices_to_json(&record.ring_indices),
+                indices_to_json(&record.distinct_ring_indices),
+                record.block_height.map(|h| h as i64),
+                indices_to_json(&fork_indices),
+            ]).expect("Unable to insert trimmed ring");
+        }
+    }
+    tx.commit().expect("Unable to commit trimmed rings");
+    summary
+}
+
+// Intersects the trimmed rings of a key image across all the given forks
+// and stores the result in the fork_indices column of the main chain.
+// Returns the number of key images updated.
+pub fn combine_fork_intersections(
+    conn: &mut Connection,
+    forks: &[String],
+) -> usize {
+    let mut intersections: HashMap<String, Vec<u64>> = HashMap::new();
+    for fork in forks {
+        for record in read_keyimages(conn, &fork_keyimages_table(fork)) {
+            let fork_indices = record.fork_indices.unwrap_or_default();
+            intersections.entry(record.image)
+                .and_modify(|indices| indices.retain(|i| fork_indices.contains(i)))
+                .or_insert(fork_indices);
+        }
+    }
+
+    let tx = conn.transaction().unwrap();
+    {
+        let mut stmt = tx.prepare(&format!(
+            "UPDATE {} SET fork_indices = ?1 WHERE image = ?2",
+            keyimages_table(MAIN_CHAIN),
+        )).unwrap();
+        for (image, indices) in &intersections {
+            stmt.execute(params![indices_to_json(indices), image]).expect("Unable to update fork indices");
+        }
+    }
+    tx.commit().expect("Unable to commit fork indices");
+    intersections.len()
+}
+
+// Result of an attack for one key image, stored in the columns added to
+// the keyimages table
+pub struct AttackResult {
+    pub image: String,
+    pub post_dm_ring_indices: Vec<u64>,
+    pub traced_output: Option<u64>,
+    pub closed_set_id: Option<u64>,
+    pub attack: String,
+}
+
+pub const RESULT_COLUMNS: [(&str, &str); 4] = [
+    ("post_dm_ring_indices", "INTEGER[]"),
+    ("traced_output", "BIGINT"),
+    ("closed_set_id", "INTEGER"),
+    ("attack", "VARCHAR(32)"),
+];
+
+// Adds the result columns to the keyimages table of a chain if they are
+// missing
+pub fn add_result_columns(
+    conn: &Connection,
+    chain: &str,
+) {
+    let table = keyimages_table(chain);
+    let mut stmt = conn.prepare(&format!("SELECT name FROM pragma_table_info('{}')", table)).unwrap();
+    let columns: HashSet<String> = stmt.query_map([], |row| row.get(0))
+        .expect("Unable to read table columns")
+        .map(|r| r.unwrap())
+        .collect();
+
+    for (column, sql_type) in RESULT_COLUMNS {
+        if !columns.contains(column) {
+            // Ring indices are JSON arrays in SQLite
+            let sqlite_type = if sql_type.ends_with("[]") { "TEXT" } else { sql_type };
+            conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, sqlite_type))
+                .expect("Unable to add result column");
+        }
+    }
+}
+
+// Stores attack results in the keyimages table of a chain. Returns the
+// number of key images updated.
+pub fn write_attack_results(
+    conn: &mut Connection,
+    chain: &str,
+    results: &[AttackResult],
+) -> usize {
+    add_result_columns(conn, chain);
+    let mut num_updated = 0_usize;
+
+    let tx = conn.transaction().unwrap();
+    {
+        let mut stmt = tx.prepare(&format!(
+            "UPDATE {} SET post_dm_ring_indices = ?1, traced_output = ?2, closed_set_id = ?3, attack = ?4 WHERE image = ?5",
+            keyimages_table(chain),
+        )).unwrap();
+        for result in results {
+            num_updated += stmt.execute(params![
+                indices_to_json(&result.post_dm_ring_indices),
+                result.traced_output.map(|i| i as i64),
+                result.closed_set_id.map(|i| i as i64),
+                result.attack,
+                result.image,
+            ]).expect("Unable to update attack result");
+        }
+    }
+    tx.commit().expect("Unable to commit attack results");
+    num_updated
+}
@@ -0,0 +1,857 @@
+// Dulmage-Mendelsohn decomposition of the transaction graph.
+//
+// The transaction graph is stored as a sparse matrix whose rows are public
+// keys and whose columns are key images. A non-zero entry at (pk, ki) means
+// that the public key pk appears in the ring of the key image ki.
+
+use std::{fs::File, io::{BufWriter, Write}};
+
+use petgraph::{graphmap::DiGraphMap, algo::tarjan_scc};
+use sprs::{CsMatI, TriMatBase};
+
+use crate::{cn_txgraph_maximum_matching, cn_txgraph_augment_matching, read_lines};
+
+pub struct DmDecomposition {
+    // pk_mates[pk] is the key image matched to pk (u32::MAX if unmatched)
+    pub pk_mates: Vec<u32>,
+    // ki_mates[ki] is the public key matched to ki (u32::MAX if unmatched)
+    pub ki_mates: Vec<u32>,
+    // Public keys reachable by alternating paths from unmatched public keys
+    pub reachable_pubkeys: Vec<bool>,
+    // Key images reachable by alternating paths from unmatched public keys
+    pub reachable_keyimages: Vec<bool>,
+    // Public keys in each block of the fine decomposition of the square
+    // submatrix. The key images of a block are the mates of its public keys.
+    // The blocks are in the order returned by tarjan_scc.
+    pub fine_blocks: Vec<Vec<u32>>,
+}
+
+// Spent status of a public key. A public key that is not reachable from
+// the unmatched public keys is matched in every maximum matching, so its
+// output is definitely spent. If it is in a singleton fine block, the key
+// image that spends it is also known.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpentStatus {
+    PossiblyUnspent,
+    DefinitelySpent,
+    KnownSpender,
+}
+
+impl SpentStatus {
+    // Code written to spent status files
+    pub fn code(&self) -> u8 {
+        match self {
+            SpentStatus::PossiblyUnspent => 0,
+            SpentStatus::DefinitelySpent => 1,
+            SpentStatus::KnownSpender => 2,
+        }
+    }
+}
+
+impl DmDecomposition {
+    pub fn num_matched(&self) -> usize {
+        self.pk_mates.iter().filter(|ki| **ki != u32::MAX).count()
+    }
+
+    pub fn num_singletons(&self) -> usize {
+        self.fine_blocks.iter().filter(|b| b.len() == 1).count()
+    }
+
+    // Marks the key images in singleton fine blocks, which are traceable
+    pub fn traced_keyimages(&self) -> Vec<bool> {
+        let mut traced = vec![false; self.ki_mates.len()];
+        for block in self.fine_blocks.iter().filter(|b| b.len() == 1) {
+            traced[self.pk_mates[block[0] as usize] as usize] = true;
+        }
+        traced
+    }
+
+    // Classifies every public key using the coarse decomposition and the
+    // singleton fine blocks
+    pub fn spent_statuses(&self) -> Vec<SpentStatus> {
+        let mut statuses: Vec<SpentStatus> = self.reachable_pubkeys.iter()
+            .map(|r| if *r { SpentStatus::PossiblyUnspent } else { SpentStatus::DefinitelySpent })
+            .collect();
+        for block in self.fine_blocks.iter().filter(|b| b.len() == 1) {
+            statuses[block[0] as usize] = SpentStatus::KnownSpender;
+        }
+        statuses
+    }
+
+    // Returns the index of the fine block containing each public key,
+    // with u32::MAX for public keys that are not in any fine block
+    pub fn pk_block_ids(&self) -> Vec<u32> {
+        let mut block_ids = vec![u32::MAX; self.pk_mates.len()];
+        for (b, block) in self.fine_blocks.iter().enumerate() {
+            for pk in block {
+                block_ids[*pk as usize] = b as u32;
+            }
+        }
+        block_ids
+    }
+
+    // Returns the index of the fine block containing a key image given
+    // the block indices returned by pk_block_ids
+    pub fn ki_block_id(&self, ki: u32, pk_block_ids: &[u32]) -> u32 {
+        match self.ki_mates.get(ki as usize) {
+            Some(pk) if *pk != u32::MAX => pk_block_ids[*pk as usize],
+            _ => u32::MAX,
+        }
+    }
+}
+
+// Returns the number of ring members of a key image that remain after the
+// public keys of every closed set other than its own are removed. This is
+// the size of the ring written by dmdec to the post DM decomposition file.
+pub fn post_dm_ring_size(
+    ring: &[u32],
+    ki_block_id: u32,
+    pk_block_ids: &[u32],
+) -> usize {
+    ring.iter()
+        .filter(|pk| {
+            let b = pk_block_ids[**pk as usize];
+            b == u32::MAX || b == ki_block_id
+        })
+        .count()
+}
+
+// Inverts the matching given by pk_mates
+pub fn ki_mates_from_pk_mates(
+    pk_mates: &[u32],
+    num_kis: usize,
+) -> Vec<u32> {
+    let mut ki_mates = vec![u32::MAX; num_kis];
+    for (pk, ki) in pk_mates.iter().enumerate() {
+        if *ki != u32::MAX {
+            ki_mates[*ki as usize] = pk as u32;
+        }
+    }
+    ki_mates
+}
+
+// Builds the transaction graph from the edge vectors returned by read_edges
+pub fn build_txgraph(
+    pk_indices: Vec<u32>,
+    ki_indices: Vec<u32>,
+    num_pks: usize,
+    num_kis: usize,
+) -> CsMatI<u8, u32> {
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let data = vec![1_u8; pk_indices.len()];
+    let tripmat: TriMatBase<Vec<u32>, Vec<u8>> = TriMatBase::from_triplets(
+        (num_pks, num_kis),
+        pk_indices,
+        ki_indices,
+        data,
+    );
+    tripmat.to_csc()
+}
+
+// Finds the sets of public keys and key images reachable from unmatched
+// public keys (rows) by alternating paths. A path goes from a public key to
+// any key image whose ring contains it, and from a key image to its mate.
+pub fn find_reachable_sets(
+    g_csr: &CsMatI<u8, u32>,
+    pk_mates: &[u32],
+    ki_mates: &[u32],
+) -> (Vec<bool>, Vec<bool>) {
+    let num_pks = pk_mates.len();
+    let num_kis = ki_mates.len();
+    let mut queue = vec![u32::MAX; num_pks];
+    let mut reachable_pubkeys = vec![false; num_pks];
+    let mut reachable_keyimages = vec![false; num_kis];
+
+    let mut qhead = 0_usize;
+    let mut qtail = 0_usize;
+
+    for i in 0..num_pks {
+        if pk_mates[i] == u32::MAX {
+            reachable_pubkeys[i] = true;
+            queue[qtail] = i as u32;
+            qtail += 1;
+        }
+    }
+
+    let csr_row_ptr = g_csr.indptr();
+    let csr_row_ptr_slice = csr_row_ptr.as_slice().unwrap();
+    let csr_col_ptr_slice = g_csr.indices();
+
+    while qhead < qtail {
+        let row_idx = queue[qhead]; // row_idx points to a pubkey
+        qhead += 1;
+        let start = csr_row_ptr_slice[row_idx as usize] as usize;
+        let end = csr_row_ptr_slice[(row_idx+1) as usize] as usize;
+
+        for col_idx in &csr_col_ptr_slice[start..end] { // col_idx points to a keyimage
+            if reachable_keyimages[*col_idx as usize] {
+                continue;
+            }
+            reachable_keyimages[*col_idx as usize] = true;
+
+            let row_mate = ki_mates[*col_idx as usize]; //row_mate is a pubkey
+            if row_mate == u32::MAX || reachable_pubkeys[row_mate as usize] {
+                continue;
+            }
+            reachable_pubkeys[row_mate as usize] = true;
+            queue[qtail] = row_mate;
+            qtail += 1;
+        }
+    }
+
+    (reachable_pubkeys, reachable_keyimages)
+}
+
+// Calculates a minimum vertex cover of the transaction graph from a maximum
+// matching given as row_mates (König's theorem). The cover has the public
+// keys that are not reachable from unmatched public keys by alternating
+// paths and the key images that are. Returns the public keys and the key
+// images in the cover.
+pub fn minimum_vertex_cover(
+    g: &CsMatI<u8, u32>,
+    row_mates: &[u32],
+) -> (Vec<bool>, Vec<bool>) {
+    let ki_mates = ki_mates_from_pk_mates(row_mates, g.cols());
+    let (reachable_pubkeys, reachable_keyimages) = find_reachable_sets(&g.to_csr(), row_mates, &ki_mates);
+    let pk_cover = reachable_pubkeys.iter().map(|r| !r).collect();
+    (pk_cover, reachable_keyimages)
+}
+
+// Checks that row_mates is a maximum matching of the transaction graph.
+// The matching must only use edges of the graph and match every key image
+// at most once. It is maximum if the vertex cover calculated from it covers
+// every edge and has as many vertices as the matching has edges, since no
+// matching can be larger than a vertex cover.
+pub fn verify_maximum_matching(
+    g: &CsMatI<u8, u32>,
+    row_mates: &[u32],
+) -> bool {
+    if row_mates.len() != g.rows() {
+        return false;
+    }
+    let mut ki_matched = vec![false; g.cols()];
+    for (pk, ki) in row_mates.iter().enumerate() {
+        if *ki == u32::MAX {
+            continue;
+        }
+        if *ki as usize >= g.cols() || ki_matched[*ki as usize] || g.get(pk, *ki as usize).is_none() {
+            return false;
+        }
+        ki_matched[*ki as usize] = true;
+    }
+    let matching_size = ki_matched.iter().filter(|m| **m).count();
+
+    let (pk_cover, ki_cover) = minimum_vertex_cover(g, row_mates);
+    let cover_size = pk_cover.iter().filter(|c| **c).count() + ki_cover.iter().filter(|c| **c).count();
+    let covers_every_edge = g.outer_iterator().enumerate().all(|(ki, ring)| {
+        ki_cover[ki] || ring.indices().iter().all(|pk| pk_cover[*pk as usize])
+    });
+
+    covers_every_edge && cover_size == matching_size
+}
+
+// Builds the directed graph on the public keys of the square submatrix whose
+// strongly connected components are the blocks of the fine decomposition.
+// There is an edge from the mate of a key image to every other public key
+// in the ring of that key image. The public keys marked in excluded_pubkeys
+// are left out of the graph. These are the public keys reachable from the
+// unmatched public keys, along with any public keys whose blocks are
+// already known.
+pub fn fine_decomposition_graph(
+    g_csr: &CsMatI<u8, u32>,
+    ki_mates: &[u32],
+    excluded_pubkeys: &[bool],
+    reachable_keyimages: &[bool],
+) -> DiGraphMap<u32, ()> {
+    let csr_row_ptr = g_csr.indptr();
+    let csr_row_ptr_slice = csr_row_ptr.as_slice().unwrap();
+    let csr_col_ptr_slice = g_csr.indices();
+    let mut fd_graph = DiGraphMap::<u32, ()>::new();
+
+    // Iterating over all public keys in the square submatrix
+    // and building the directed graph
+    for pk_idx in 0..excluded_pubkeys.len() {
+        if excluded_pubkeys[pk_idx] {
+            continue;
+        }
+        fd_graph.add_node(pk_idx as u32);
+        let start = csr_row_ptr_slice[pk_idx] as usize;
+        let end = csr_row_ptr_slice[pk_idx+1] as usize;
+        for ki_idx in &csr_col_ptr_slice[start..end] { // This points to a keyimage
+            let ki_mate = ki_mates[*ki_idx as usize];
+            if !reachable_keyimages[*ki_idx as usize] && ki_mate != u32::MAX && ki_mate != pk_idx as u32 {
+                fd_graph.add_edge(ki_mate, pk_idx as u32, ());
+            }
+        }
+    }
+    fd_graph
+}
+
+// Calculates the Dulmage-Mendelsohn decomposition of the transaction graph
+// given as a CSC matrix with public keys as rows and key images as columns
+pub fn dm_decomposition(
+    g: &CsMatI<u8, u32>,
+) -> DmDecomposition {
+    let pk_mates = cn_txgraph_maximum_matching(g);
+    dm_decomposition_from_matching(g, pk_mates)
+}
+
+// Calculates the Dulmage-Mendelsohn decomposition of the transaction graph
+// from an already computed maximum matching
+pub fn dm_decomposition_from_matching(
+    g: &CsMatI<u8, u32>,
+    pk_mates: Vec<u32>,
+) -> DmDecomposition {
+    let num_kis = g.cols();
+    let ki_mates = ki_mates_from_pk_mates(&pk_mates, num_kis);
+
+    let g_csr = g.to_csr();
+    let (reachable_pubkeys, reachable_keyimages) = find_reachable_sets(&g_csr, &pk_mates, &ki_mates);
+    let fd_graph = fine_decomposition_graph(&g_csr, &ki_mates, &reachable_pubkeys, &reachable_keyimages);
+
+    // Finding strongly connected components
+    let fine_blocks = tarjan_scc(&fd_graph);
+
+    DmDecomposition {
+        pk_mates,
+        ki_mates,
+        reachable_pubkeys,
+        reachable_keyimages,
+        fine_blocks,
+    }
+}
+
+// Returns the condensation DAG of the fine blocks and the depth of each
+// block. Blocks are identified by their index in fine_blocks. There is an
+// edge (a, b) if a key image in block a has a ring member in block b, so
+// the members of b are removed from the rings of a when b is identified as
+// a closed set. The depth of a block is the length of the longest path
+// from it, which is 0 for blocks whose rings lie entirely in the block.
+pub fn fine_block_dag(
+    g: &CsMatI<u8, u32>,
+    dmd: &DmDecomposition,
+) -> (Vec<(u32, u32)>, Vec<u32>) {
+    let pk_block_ids = dmd.pk_block_ids();
+    let mut dag_edges: Vec<(u32, u32)> = vec![];
+    let mut depths = vec![0_u32; dmd.fine_blocks.len()];
+
+    // tarjan_scc returns the blocks in reverse topological order, so the
+    // depths of the successors of a block are known when it is reached
+    for (a, block) in dmd.fine_blocks.iter().enumerate() {
+        let mut successors: Vec<u32> = vec![];
+        for pk in block {
+            let ki = dmd.pk_mates[*pk as usize];
+            for ring_pk in g.outer_view(ki as usize).unwrap().indices() {
+                let b = pk_block_ids[*ring_pk as usize];
+                if b != u32::MAX && b != a as u32 {
+                    successors.push(b);
+                }
+            }
+        }
+        successors.sort_unstable();
+        successors.dedup();
+
+        for b in successors {
+            assert!((b as usize) < a, "Fine blocks are not in reverse topological order");
+            depths[a] = depths[a].max(depths[b as usize] + 1);
+            dag_edges.push((a as u32, b));
+        }
+    }
+    (dag_edges, depths)
+}
+
+// Writes the condensation DAG of the fine blocks to a file.
+// The first line has the number of blocks and the number of edges separated
+// by a single space. Each of the next lines has a block index, its size and
+// its depth. Each remaining line has the two block indices of an edge.
+pub fn write_fine_block_dag(
+    dmd: &DmDecomposition,
+    dag_edges: &[(u32, u32)],
+    depths: &[u32],
+    filename: &str,
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(filename).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    writeln!(buf, "{} {}", dmd.fine_blocks.len(), dag_edges.len()).expect(file_err_msg);
+    for (b, block) in dmd.fine_blocks.iter().enumerate() {
+        writeln!(buf, "{} {} {}", b, block.len(), depths[b]).expect(file_err_msg);
+    }
+    for (a, b) in dag_edges {
+        writeln!(buf, "{} {}", a, b).expect(file_err_msg);
+    }
+}
+
+// Permutations that put the key image x public key matrix, i.e. the
+// transpose of the transaction graph, into block upper triangular form.
+// The fields have the meaning of those returned by cs_dmperm of CSparse:
+// row p[k] of the matrix is row k of the permuted matrix and column q[k]
+// is its column k. Fine block b has the rows r[b]..r[b+1] and the columns
+// s[b]..s[b+1] of the permuted matrix. The coarse decomposition has the
+// row sets R1, R2, R3, R0 delimited by rr and the column sets C0, C1, C2,
+// C3 delimited by cc, where C0 are the unmatched public keys and R0 are the
+// unmatched key images.
+pub struct BlockTriangularForm {
+    pub p: Vec<u32>,
+    pub q: Vec<u32>,
+    pub r: Vec<usize>,
+    pub s: Vec<usize>,
+    pub rr: [usize; 5],
+    pub cc: [usize; 5],
+}
+
+// Finds the key images and public keys reachable from unmatched key images
+// by alternating paths. A path goes from a key image to the public keys in
+// its ring, and from a public key to its mate.
+fn find_reachable_from_unmatched_keyimages(
+    g: &CsMatI<u8, u32>,
+    pk_mates: &[u32],
+    ki_mates: &[u32],
+) -> (Vec<bool>, Vec<bool>) {
+    let mut reachable_pubkeys = vec![false; pk_mates.len()];
+    let mut reachable_keyimages = vec![false; ki_mates.len()];
+    let mut queue: Vec<u32> = (0..ki_mates.len() as u32).filter(|ki| ki_mates[*ki as usize] == u32::MAX).collect();
+    for ki in &queue {
+        reachable_keyimages[*ki as usize] = true;
+    }
+
+    let mut qhead = 0_usize;
+    while qhead < queue.len() {
+        let ki = queue[qhead];
+        qhead += 1;
+        for pk in g.outer_view(ki as usize).unwrap().indices() {
+            if reachable_pubkeys[*pk as usize] {
+                continue;
+            }
+            reachable_pubkeys[*pk as usize] = true;
+            let pk_mate = pk_mates[*pk as usize];
+            if pk_mate != u32::MAX && !reachable_keyimages[pk_mate as usize] {
+                reachable_keyimages[pk_mate as usize] = true;
+                queue.push(pk_mate);
+            }
+        }
+    }
+    (reachable_pubkeys, reachable_keyimages)
+}
+
+// Calculates the block triangular form from a DM decomposition of the
+// transaction graph g
+pub fn block_triangular_form(
+    g: &CsMatI<u8, u32>,
+    dmd: &DmDecomposition,
+) -> BlockTriangularForm {
+    let num_pks = dmd.pk_mates.len();
+    let num_kis = dmd.ki_mates.len();
+    let (vertical_pubkeys, _vertical_keyimages) = find_reachable_from_unmatched_keyimages(g, &dmd.pk_mates, &dmd.ki_mates);
+
+    let mut p: Vec<u32> = Vec::with_capacity(num_kis);
+    let mut q: Vec<u32> = Vec::with_capacity(num_pks);
+    let mut r: Vec<usize> = vec![0];
+    let mut s: Vec<usize> = vec![0];
+
+    // C0 followed by C1 and R1, in the order of the public keys
+    q.extend((0..num_pks as u32).filter(|pk| dmd.pk_mates[*pk as usize] == u32::MAX));
+    let c0_len = q.len();
+    for pk in 0..num_pks as u32 {
+        let ki = dmd.pk_mates[pk as usize];
+        if ki != u32::MAX && dmd.reachable_pubkeys[pk as usize] {
+            q.push(pk);
+            p.push(ki);
+        }
+    }
+    let (c1_end, r1_end) = (q.len(), p.len());
+    if c1_end > 0 {
+        r.push(r1_end);
+        s.push(c1_end);
+    }
+
+    // Fine blocks of the square submatrix in topological order, which is
+    // the reverse of the order returned by tarjan_scc. The blocks reachable
+    // from unmatched key images belong to C3.
+    for block in dmd.fine_blocks.iter().rev() {
+        if vertical_pubkeys[block[0] as usize] {
+            continue;
+        }
+        for pk in block {
+            q.push(*pk);
+            p.push(dmd.pk_mates[*pk as usize]);
+        }
+        r.push(p.len());
+        s.push(q.len());
+    }
+    let (c2_end, r2_end) = (q.len(), p.len());
+
+    // C3 and R3 followed by R0
+    for pk in 0..num_pks as u32 {
+        if vertical_pubkeys[pk as usize] {
+            q.push(pk);
+            p.push(dmd.pk_mates[pk as usize]);
+        }
+    }
+    let r3_end = p.len();
+    p.extend((0..num_kis as u32).filter(|ki| dmd.ki_mates[*ki as usize] == u32::MAX));
+    if r2_end < num_kis {
+        r.push(num_kis);
+        s.push(num_pks);
+    }
+    assert_eq!(p.len(), num_kis);
+    assert_eq!(q.len(), num_pks);
+    assert_eq!(r.len(), s.len());
+
+    BlockTriangularForm {
+        p,
+        q,
+        r,
+        s,
+        rr: [0, r1_end, r2_end, r3_end, num_kis],
+        cc: [0, c0_len, c1_end, c2_end, num_pks],
+    }
+}
+
+// Writes the block triangular form to a file.
+// The first line has the number of rows (key images), the number of columns
+// (public keys) and the number of fine blocks separated by a single space.
+// The following lines have p, q, r, s, rr and cc in this order, with the
+// entries of each separated by a single space.
+pub fn write_block_triangular_form(
+    btf: &BlockTriangularForm,
+    filename: &str,
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(filename).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    let join = |v: &mut dyn Iterator<Item = String>| v.collect::<Vec<String>>().join(" ");
+    writeln!(buf, "{} {} {}", btf.p.len(), btf.q.len(), btf.r.len() - 1).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.p.iter().map(|x| x.to_string()))).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.q.iter().map(|x| x.to_string()))).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.r.iter().map(|x| x.to_string()))).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.s.iter().map(|x| x.to_string()))).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.rr.iter().map(|x| x.to_string()))).expect(file_err_msg);
+    writeln!(buf, "{}", join(&mut btf.cc.iter().map(|x| x.to_string()))).expect(file_err_msg);
+}
+
+// State of a DM decomposition that is saved between runs so that the
+// decomposition can be updated when new key images and public keys are
+// appended to the transaction graph.
+pub struct DmState {
+    pub num_pks: usize,
+    pub num_kis: usize,
+    pub pk_mates: Vec<u32>,
+    // Fingerprint of the ring of each key image, used to detect rings that
+    // changed since the state was saved
+    pub ring_fingerprints: Vec<u64>,
+    pub fine_blocks: Vec<Vec<u32>>,
+}
+
+impl DmState {
+    // The decomposition must be the one of the transaction graph g
+    pub fn from_decomposition(g: &CsMatI<u8, u32>, dmd: &DmDecomposition) -> DmState {
+        DmState {
+            num_pks: dmd.pk_mates.len(),
+            num_kis: dmd.ki_mates.len(),
+            pk_mates: dmd.pk_mates.clone(),
+            ring_fingerprints: ring_fingerprints(g, dmd.ki_mates.len()),
+            fine_blocks: dmd.fine_blocks.clone(),
+        }
+    }
+}
+
+// Hashes the ring size and the sorted public keys of a ring using 64-bit
+// FNV-1a, which unlike the hasher of the standard library is stable across
+// Rust versions
+pub fn ring_fingerprint(
+    ring: &[u32],
+) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for x in std::iter::once(ring.len() as u32).chain(ring.iter().copied()) {
+        for byte in x.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
+// Returns the fingerprints of the rings of the first num_kis key images
+fn ring_fingerprints(
+    g: &CsMatI<u8, u32>,
+    num_kis: usize,
+) -> Vec<u64> {
+    (0..num_kis)
+        .map(|ki| g.outer_view(ki).map_or(ring_fingerprint(&[]), |col| ring_fingerprint(col.indices())))
+        .collect()
+}
+
+// Writes the DM state to a file.
+// The first line has the number of public keys, the number of key images
+// and the number of fine blocks separated by a single space. The second line
+// has the key image matched to each public key, with u32::MAX for unmatched
+// public keys. The third line has the ring fingerprint of each key image.
+// Each subsequent line has the public keys of one fine block.
+pub fn write_dm_state(
+    state: &DmState,
+    filename: &str,
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(filename).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    writeln!(buf, "{} {} {}", state.num_pks, state.num_kis, state.fine_blocks.len()).expect(file_err_msg);
+    let mates: Vec<String> = state.pk_mates.iter().map(|ki| ki.to_string()).collect();
+    writeln!(buf, "{}", mates.join(" ")).expect(file_err_msg);
+    let fingerprints: Vec<String> = state.ring_fingerprints.iter().map(|f| f.to_string()).collect();
+    writeln!(buf, "{}", fingerprints.join(" ")).expect(file_err_msg);
+    for block in &state.fine_blocks {
+        let pks: Vec<String> = block.iter().map(|pk| pk.to_string()).collect();
+        writeln!(buf, "{}", pks.join(" ")).expect(file_err_msg);
+    }
+}
+
+// Reads a DM state written by write_dm_state.
+// Returns None if the file cannot be opened.
+pub fn read_dm_state(
+    filename: &str,
+) -> Option<DmState> {
+    let mut lines = read_lines(filename).ok()?.map_while(Result::ok);
+    let dim_str = lines.next()?;
+    let items: Vec<usize> = dim_str.split_whitespace().map(|x| x.parse::<usize>().unwrap()).collect();
+    assert_eq!(items.len(), 3);
+    let (num_pks, num_kis, num_blocks) = (items[0], items[1], items[2]);
+
+    let pk_mates: Vec<u32> = lines.next().unwrap_or_default()
+        .split_whitespace()
+        .map(|x| x.parse::<u32>().unwrap())
+        .collect();
+    assert_eq!(pk_mates.len(), num_pks);
+
+    let ring_fingerprints: Vec<u64> = lines.next().unwrap_or_default()
+        .split_whitespace()
+        .map(|x| x.parse::<u64>().unwrap())
+        .collect();
+    assert_eq!(ring_fingerprints.len(), num_kis);
+
+    let mut fine_blocks: Vec<Vec<u32>> = Vec::with_capacity(num_blocks);
+    for line in lines.take(num_blocks) {
+        fine_blocks.push(line.split_whitespace().map(|x| x.parse::<u32>().unwrap()).collect());
+    }
+    assert_eq!(fine_blocks.len(), num_blocks);
+
+    Some(DmState { num_pks, num_kis, pk_mates, ring_fingerprints, fine_blocks })
+}
+
+// Updates a DM decomposition after key images (columns) and public keys
+// (rows) have been appended to the transaction graph. The rings of the key
+// images in the previous state must not have changed, which is checked
+// using their fingerprints.
+//
+// The union of the old fine blocks is closed: the rings of their key images
+// only contain public keys of old blocks, and these public keys are matched
+// to these key images. New key images can have public keys of old blocks in
+// their rings, but an alternating path that enters the old blocks cannot
+// leave them and never reaches an unmatched public key. So no augmenting
+// path goes through the old blocks and their matching is kept. The matching
+// is augmented starting from the new key images only.
+//
+// The rows of the public keys outside the old blocks only contain key
+// images outside the old blocks. So the reachable sets and the fine
+// decomposition graph are calculated on the submatrix of these public keys
+// and key images, which gives the same result as on the whole graph. The
+// old blocks have no edges to the other public keys, so they stay blocks of
+// the new decomposition and the strongly connected components are only
+// calculated on the public keys outside them.
+//
+// Returns None if the previous state is inconsistent with the graph, in
+// which case the decomposition has to be calculated from scratch.
+pub fn dm_decomposition_incremental(
+    g: &CsMatI<u8, u32>,
+    prev: &DmState,
+) -> Option<DmDecomposition> {
+    let num_pks = g.rows();
+    let num_kis = g.cols();
+    if prev.num_pks > num_pks || prev.num_kis > num_kis || prev.pk_mates.len() != prev.num_pks
+        || prev.ring_fingerprints.len() != prev.num_kis {
+        return None;
+    }
+
+    // Check that the rings of the previous key images are unchanged. Edge
+    // rewrites such as known spends or temporal pruning change old rings.
+    if ring_fingerprints(g, prev.num_kis) != prev.ring_fingerprints {
+        return None;
+    }
+    // Check that every matched edge of the previous state is still an edge
+    for (pk, ki) in prev.pk_mates.iter().enumerate() {
+        if *ki != u32::MAX && (*ki as usize >= prev.num_kis || g.get(pk, *ki as usize).is_none()) {
+            return None;
+        }
+    }
+
+    // Public keys and key images of the old blocks
+    let mut old_block_pks = vec![false; num_pks];
+    let mut old_block_kis = vec![false; num_kis];
+    for block in &prev.fine_blocks {
+        for pk in block {
+            let ki = *prev.pk_mates.get(*pk as usize)?;
+            if ki == u32::MAX {
+                return None;
+            }
+            old_block_pks[*pk as usize] = true;
+            old_block_kis[ki as usize] = true;
+        }
+    }
+
+    let mut pk_mates = prev.pk_mates.clone();
+    pk_mates.resize(num_pks, u32::MAX);
+    cn_txgraph_augment_matching(g, &mut pk_mates, prev.num_kis);
+
+    let ki_mates = ki_mates_from_pk_mates(&pk_mates, num_kis);
+
+    let sub_csr = submatrix_outside_blocks(g, &old_block_pks, &old_block_kis);
+    let (reachable_pubkeys, reachable_keyimages) = find_reachable_sets(&sub_csr, &pk_mates, &ki_mates);
+
+    let mut excluded_pubkeys = reachable_pubkeys.clone();
+    for (pk, old) in old_block_pks.iter().enumerate() {
+        if *old {
+            excluded_pubkeys[pk] = true;
+        }
+    }
+    let fd_graph = fine_decomposition_graph(&sub_csr, &ki_mates, &excluded_pubkeys, &reachable_keyimages);
+
+    // The previous blocks have no edges to the new blocks. So they come first
+    // in the reverse topological order returned by tarjan_scc.
+    let mut fine_blocks = prev.fine_blocks.clone();
+    fine_blocks.extend(tarjan_scc(&fd_graph));
+
+    Some(DmDecomposition {
+        pk_mates,
+        ki_mates,
+        reachable_pubkeys,
+        reachable_keyimages,
+        fine_blocks,
+    })
+}
+
+// Returns the submatrix of the transaction graph without the public keys
+// and key images of the old blocks as a CSR matrix with the same dimensions
+fn submatrix_outside_blocks(
+    g: &CsMatI<u8, u32>,
+    old_block_pks: &[bool],
+    old_block_kis: &[bool],
+) -> CsMatI<u8, u32> {
+    let mut pk_indices: Vec<u32> = vec![];
+    let mut ki_indices: Vec<u32> = vec![];
+    for (ki, ring) in g.outer_iterator().enumerate() {
+        if old_block_kis[ki] {
+            continue;
+        }
+        for pk in ring.indices().iter().filter(|pk| !old_block_pks[**pk as usize]) {
+            pk_indices.push(*pk);
+            ki_indices.push(ki as u32);
+        }
+    }
+    let data = vec![1_u8; pk_indices.len()];
+    let tripmat: TriMatBase<Vec<u32>, Vec<u8>> = TriMatBase::from_triplets(
+        (g.rows(), g.cols()),
+        pk_indices,
+        ki_indices,
+        data,
+    );
+    tripmat.to_csr()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+
+    // Builds a transaction graph from (key image, public key) edges
+    fn txgraph(edges: &[(u32, u32)], num_pks: usize, num_kis: usize) -> CsMatI<u8, u32> {
+        let pk_indices = edges.iter().map(|(_, pk)| *pk).collect();
+        let ki_indices = edges.iter().map(|(ki, _)| *ki).collect();
+        build_txgraph(pk_indices, ki_indices, num_pks, num_kis)
+    }
+
+    fn sorted_blocks(dmd: &DmDecomposition) -> Vec<Vec<u32>> {
+        let mut blocks: Vec<Vec<u32>> = dmd.fine_blocks.iter()
+            .map(|b| {
+                let mut b = b.clone();
+                b.sort_unstable();
+                b
+            })
+            .collect();
+        blocks.sort_unstable();
+        blocks
+    }
+
+    // The decomposition is unique even though the matching is not
+    fn assert_same_decomposition(a: &DmDecomposition, b: &DmDecomposition) {
+        assert_eq!(a.num_matched(), b.num_matched());
+        assert_eq!(a.reachable_pubkeys, b.reachable_pubkeys);
+        assert_eq!(a.reachable_keyimages, b.reachable_keyimages);
+        assert_eq!(sorted_blocks(a), sorted_blocks(b));
+    }
+
+    #[test]
+    fn incremental_matches_full_with_new_closed_set() {
+        // Old graph: ki0 and ki1 trace pk0 and pk1, ki2 mixes pk2 and pk3
+        let old_edges = [(0, 0), (1, 0), (1, 1), (2, 2), (2, 3)];
+        let old_g = txgraph(&old_edges, 4, 3);
+        let old_dmd = dm_decomposition(&old_g);
+        assert_eq!(sorted_blocks(&old_dmd), vec![vec![0], vec![1]]);
+        let state = DmState::from_decomposition(&old_g, &old_dmd);
+
+        // ki3 has the old block pk1 in its ring and closes the set of pk2
+        // and pk3 with ki2. ki4 spends the new public key pk4.
+        let mut edges = old_edges.to_vec();
+        edges.extend([(3, 1), (3, 2), (3, 3), (4, 3), (4, 4)]);
+        let g = txgraph(&edges, 5, 5);
+
+        let incremental = dm_decomposition_incremental(&g, &state).unwrap();
+        let full = dm_decomposition(&g);
+        assert_same_decomposition(&incremental, &full);
+        assert_eq!(sorted_blocks(&full), vec![vec![0], vec![1], vec![2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn incremental_matches_full_on_random_graphs() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..20 {
+            let num_pks = 60_usize;
+            let num_kis = 50_usize;
+            let mut edges: Vec<(u32, u32)> = vec![];
+            for ki in 0..num_kis as u32 {
+                // Later key images can only use outputs created before them
+                let max_pk = (num_pks as u32 * (ki + 1) / num_kis as u32).max(1);
+                for _ in 0..rng.gen_range(1..4) {
+                    edges.push((ki, rng.gen_range(0..max_pk)));
+                }
+            }
+            let cut = rng.gen_range(1..num_kis) as u32;
+            let old_edges: Vec<(u32, u32)> = edges.iter().filter(|(ki, _)| *ki < cut).copied().collect();
+            let old_num_pks = old_edges.iter().map(|(_, pk)| *pk as usize + 1).max().unwrap();
+            let old_g = txgraph(&old_edges, old_num_pks, cut as usize);
+            let state = DmState::from_decomposition(&old_g, &dm_decomposition(&old_g));
+
+            let g = txgraph(&edges, num_pks, num_kis);
+            let incremental = dm_decomposition_incremental(&g, &state).unwrap();
+            assert_same_decomposition(&incremental, &dm_decomposition(&g));
+        }
+    }
+
+    #[test]
+    fn incremental_rejects_changed_old_rings() {
+        let old_edges = [(0, 0), (0, 1), (1, 1), (1, 2)];
+        let old_g = txgraph(&old_edges, 3, 2);
+        let state = DmState::from_decomposition(&old_g, &dm_decomposition(&old_g));
+
+        // A known spend fixes the ring of ki1 to pk1 and ki2 is appended.
+        // The matched edges of the old state are still edges.
+        let edges = [(0, 0), (0, 1), (1, 1), (2, 2)];
+        let g = txgraph(&edges, 3, 3);
+        assert!(state.pk_mates.iter().enumerate()
+            .all(|(pk, ki)| *ki == u32::MAX || g.get(pk, *ki as usize).is_some()));
+        assert!(dm_decomposition_incremental(&g, &state).is_none());
+    }
+}
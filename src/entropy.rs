@@ -0,0 +1,83 @@
+// Effective anonymity metrics of rings.
+//
+// The number of ring members left after an attack does not account for
+// members being unequally likely to be the real spend. The Shannon entropy
+// of the spend distribution measures the average uncertainty about the
+// real spend, while the min-entropy measures the uncertainty faced by an
+// adversary who guesses the most likely member. Both are in bits and equal
+// log2 of the ring size when the distribution is uniform.
+
+use std::collections::HashMap;
+
+// Width of the histogram bins in bits
+pub const ENTROPY_BIN_WIDTH: f64 = 0.5;
+// Number of histogram bins. The last bin counts all larger entropies.
+pub const NUM_ENTROPY_BINS: usize = 9;
+
+// Shannon entropy in bits of a probability distribution
+pub fn shannon_entropy(
+    probs: &[f64],
+) -> f64 {
+    -probs.iter()
+        .filter(|p| **p > 0.0)
+        .map(|p| p * p.log2())
+        .sum::<f64>()
+}
+
+// Min-entropy in bits of a probability distribution
+pub fn min_entropy(
+    probs: &[f64],
+) -> f64 {
+    let max_prob = probs.iter().copied().fold(0.0_f64, f64::max);
+    if max_prob > 0.0 { -max_prob.log2() } else { 0.0 }
+}
+
+// Calculates the Shannon entropy and min-entropy of the spend distribution
+// of every key image. The spend distribution of a key image is taken from
+// probs if it has an entry for that key image, and is uniform over the ring
+// members otherwise. Empty rings get NaN entropies.
+pub fn ring_entropies(
+    tx_rings: &[Vec<u32>],
+    probs: &HashMap<u32, Vec<f64>>,
+) -> Vec<(f64, f64)> {
+    tx_rings.iter().enumerate()
+        .map(|(ki, ring)| {
+            if let Some(p) = probs.get(&(ki as u32)) {
+                (shannon_entropy(p), min_entropy(p))
+            }
+            else if ring.is_empty() {
+                (f64::NAN, f64::NAN)
+            }
+            else {
+                let h = (ring.len() as f64).log2();
+                (h, h)
+            }
+        })
+        .collect()
+}
+
+// Groups the probabilities of (key image, public key, probability) triples
+// by key image. The result can be passed to ring_entropies.
+pub fn group_probabilities_by_keyimage(
+    edge_probs: &[(u32, u32, f64)],
+) -> HashMap<u32, Vec<f64>> {
+    let mut probs: HashMap<u32, Vec<f64>> = HashMap::new();
+    for (ki, _pk, p) in edge_probs {
+        probs.entry(*ki).or_default().push(*p);
+    }
+    probs
+}
+
+// Histogram of entropies with bins of width ENTROPY_BIN_WIDTH bits.
+// NaN entropies are not counted.
+pub fn entropy_histogram(
+    entropies: impl Iterator<Item = f64>,
+) -> Vec<u32> {
+    let mut histogram = vec![0_u32; NUM_ENTROPY_BINS];
+    for h in entropies.filter(|h| !h.is_nan()) {
+        // Small negative values come from rounding errors
+        let bin = ((h.max(0.0) + 1e-9) / ENTROPY_BIN_WIDTH) as usize;
+        histogram[bin.min(NUM_ENTROPY_BINS - 1)] += 1;
+    }
+    histogram
+}
@@ -0,0 +1,55 @@
+// Simulation of an adversary that owns many outputs.
+//
+// An attacker who floods the chain with its own outputs knows that they
+// are not the real spends in other users' rings. Removing them from these
+// rings is side information that the cascade attack and the DM
+// decomposition can build on. A ring whose members are all owned by the
+// attacker is a spend by the attacker and is left unchanged.
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+pub struct FloodingSummary {
+    // Ring members removed because they are owned by the attacker
+    pub removed_members: usize,
+    // Rings whose members are all owned by the attacker
+    pub attacker_rings: usize,
+}
+
+// Marks each public key as owned by the attacker independently with
+// probability fraction
+pub fn sample_attacker_pks(
+    num_pks: usize,
+    fraction: f64,
+    seed: u64,
+) -> Vec<bool> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..num_pks).map(|_| rng.gen_bool(fraction)).collect()
+}
+
+// Removes the public keys owned by the attacker from all rings, except
+// from rings that would become empty
+pub fn remove_attacker_pks(
+    tx_rings: &mut [Vec<u32>],
+    attacker_owned: &[bool],
+) -> FloodingSummary {
+    let mut summary = FloodingSummary {
+        removed_members: 0,
+        attacker_rings: 0,
+    };
+    let is_owned = |pk: &u32| attacker_owned.get(*pk as usize).copied().unwrap_or(false);
+
+    for ring in tx_rings.iter_mut() {
+        if ring.is_empty() {
+            continue;
+        }
+        let num_owned = ring.iter().filter(|pk| is_owned(pk)).count();
+        if num_owned == ring.len() {
+            summary.attacker_rings += 1;
+        }
+        else {
+            ring.retain(|pk| !is_owned(pk));
+            summary.removed_members += num_owned;
+        }
+    }
+    summary
+}
@@ -0,0 +1,67 @@
+// Ring intersection across hard forks.
+//
+// Outputs created before a hard fork exist on both chains. A key image
+// that appears on both chains spends the same output on both, so its real
+// spend is in the intersection of its rings. The fork edge files use the
+// same key image and public key indices as the main chain edge file.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+#[derive(Default)]
+pub struct ForkStats {
+    // Key images of the main chain that also appear in the fork
+    pub shared_keyimages: usize,
+    // Shared key images whose ring became smaller
+    pub reduced_rings: usize,
+    // Ring members removed from the main chain rings
+    pub removed_members: usize,
+    // Shared key images whose rings have no common member. Their main
+    // chain rings are left unchanged.
+    pub empty_intersections: usize,
+    // Number of shared key images for each intersection size
+    pub intersection_size_histogram: BTreeMap<usize, u32>,
+}
+
+impl ForkStats {
+    // Shared key images whose ring intersection has a single member
+    pub fn traced_rings(&self) -> u32 {
+        *self.intersection_size_histogram.get(&1).unwrap_or(&0)
+    }
+}
+
+// Intersects the main chain rings with the rings of the same key images
+// in a fork, given as the edges of the fork. Key images of the fork that
+// are not in the main chain are ignored.
+pub fn intersect_fork_rings(
+    tx_ring_sets: &mut [HashSet<u32>],
+    fork_pk_indices: &[u32],
+    fork_ki_indices: &[u32],
+) -> ForkStats {
+    assert_eq!(fork_pk_indices.len(), fork_ki_indices.len());
+    let mut fork_rings: HashMap<u32, HashSet<u32>> = HashMap::new();
+    for (pk, ki) in fork_pk_indices.iter().zip(fork_ki_indices.iter()) {
+        if (*ki as usize) < tx_ring_sets.len() && !tx_ring_sets[*ki as usize].is_empty() {
+            fork_rings.entry(*ki).or_default().insert(*pk);
+        }
+    }
+
+    let mut stats = ForkStats {
+        shared_keyimages: fork_rings.len(),
+        ..Default::default()
+    };
+    for (ki, fork_ring) in &fork_rings {
+        let ring = &mut tx_ring_sets[*ki as usize];
+        let intersection: HashSet<u32> = ring.intersection(fork_ring).copied().collect();
+        stats.intersection_size_histogram.entry(intersection.len()).and_modify(|c| *c += 1).or_insert(1);
+
+        if intersection.is_empty() {
+            stats.empty_intersections += 1;
+        }
+        else if intersection.len() < ring.len() {
+            stats.reduced_rings += 1;
+            stats.removed_members += ring.len() - intersection.len();
+            *ring = intersection;
+        }
+    }
+    stats
+}
@@ -0,0 +1,194 @@
+// Extraction of rings from transactions decoded as JSON.
+//
+// The gettransactions RPC of monerod with decode_as_json returns each
+// transaction as a JSON string in its as_json field. Every key input of a
+// transaction has the amount, the key image and the key offsets of its
+// ring. The key offsets are relative: the first one is a global output
+// index and each subsequent one is the difference from the previous index.
+// An output is identified by its amount and its global index among the
+// outputs with that amount.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::interner::{parse_hex_identifier, Identifier};
+
+// Converts relative key offsets into absolute global output indices
+pub fn keyoffsets_to_keyindices(
+    key_offsets: &[u64],
+) -> Vec<u64> {
+    let mut key_indices: Vec<u64> = Vec::with_capacity(key_offsets.len());
+    let mut index = 0_u64;
+    for offset in key_offsets {
+        index += offset;
+        key_indices.push(index);
+    }
+    key_indices
+}
+
+// A key input of a transaction
+pub struct RingInput {
+    pub key_image: Identifier,
+    pub amount: u64,
+    // Distinct global indices of the ring members in increasing order
+    pub key_indices: Vec<u64>,
+}
+
+// Returns the transactions in a JSON document. The document can be a
+// gettransactions response with a txs array, an array of transactions or
+// a single transaction. A transaction with an as_json field is decoded
+// from that field.
+pub fn transactions_from_json(
+    doc: &Value,
+) -> Vec<Value> {
+    let txs: Vec<&Value> = match doc {
+        Value::Array(txs) => txs.iter().collect(),
+        Value::Object(obj) => match obj.get("txs") {
+            Some(Value::Array(txs)) => txs.iter().collect(),
+            _ => vec![doc],
+        },
+        _ => vec![],
+    };
+
+    txs.into_iter()
+        .map(|tx| match tx.get("as_json").and_then(Value::as_str) {
+            Some(as_json) => serde_json::from_str(as_json).expect("Invalid as_json field"),
+            None => tx.clone(),
+        })
+        .collect()
+}
+
+// Returns the key inputs of a transaction. Coinbase (gen) inputs are
+// skipped.
+pub fn ring_inputs(
+    tx: &Value,
+) -> Vec<RingInput> {
+    let mut inputs: Vec<RingInput> = vec![];
+
+    if let Some(Value::Array(vin)) = tx.get("vin") {
+        for txin in vin {
+            if let Some(key) = txin.get("key") {
+                let amount = key["amount"].as_u64().expect("Invalid input amount");
+                let key_image = key["k_image"].as_str()
+                    .and_then(parse_hex_identifier)
+                    .expect("Invalid key image");
+                let key_offsets: Vec<u64> = key["key_offsets"].as_array()
+                    .expect("Invalid key offsets")
+                    .iter()
+                    .map(|x| x.as_u64().expect("Invalid key offset"))
+                    .collect();
+
+                let mut key_indices = keyoffsets_to_keyindices(&key_offsets);
+                key_indices.sort_unstable();
+                key_indices.dedup();
+                inputs.push(RingInput {
+                    key_image,
+                    amount,
+                    key_indices,
+                });
+            }
+        }
+    }
+    inputs
+}
+
+// The amount under which the global index of an output is counted.
+// Outputs of RingCT transactions (version 2 and later) are counted
+// under the amount 0, including coinbase outputs.
+pub fn output_amount_key(
+    tx_version: u64,
+    amount: u64,
+) -> u64 {
+    if tx_version >= 2 { 0 } else { amount }
+}
+
+// Returns the amount and public key of each output of a transaction in
+// the order of the outputs
+pub fn tx_outputs(
+    tx: &Value,
+) -> Vec<(u64, Identifier)> {
+    let mut outputs: Vec<(u64, Identifier)> = vec![];
+
+    if let Some(Value::Array(vout)) = tx.get("vout") {
+        for txout in vout {
+            let amount = txout["amount"].as_u64().expect("Invalid output amount");
+            let target = &txout["target"];
+            // Outputs with view tags have a tagged_key target
+            let key = target.get("key")
+                .or_else(|| target.get("tagged_key").and_then(|t| t.get("key")))
+                .and_then(Value::as_str)
+                .and_then(parse_hex_identifier)
+                .expect("Invalid output key");
+            outputs.push((amount, key));
+        }
+    }
+    outputs
+}
+
+// Returns the transactions of a gettransactions response keyed by their
+// hashes
+pub fn transactions_by_hash(
+    doc: &Value,
+) -> HashMap<String, Value> {
+    let mut txs: HashMap<String, Value> = HashMap::new();
+
+    if let Some(Value::Array(entries)) = doc.get("txs") {
+        for entry in entries {
+            let tx_hash = entry["tx_hash"].as_str().expect("Missing transaction hash");
+            let as_json = entry["as_json"].as_str().expect("Missing as_json field");
+            txs.insert(tx_hash.to_string(), serde_json::from_str(as_json).expect("Invalid as_json field"));
+        }
+    }
+    txs
+}
+
+// Returns the decoded block of a getblock response, which has the miner
+// transaction and the hashes of the other transactions
+pub fn block_from_json(
+    doc: &Value,
+) -> Value {
+    let block_json = doc["result"]["json"].as_str().expect("Missing block json field");
+    serde_json::from_str(block_json).expect("Invalid block json field")
+}
+
+// Indices assigned to outputs during ingestion. Outputs are numbered in
+// the order they are created, and each output also has a global index
+// among the outputs with the same amount key.
+#[derive(Default)]
+pub struct OutputIndex {
+    pk_indices: HashMap<(u64, u64), u32>,
+    num_outputs_per_amount: HashMap<u64, u64>,
+}
+
+impl OutputIndex {
+    pub fn new() -> OutputIndex {
+        OutputIndex::default()
+    }
+
+    pub fn num_outputs(&self) -> usize {
+        self.pk_indices.len()
+    }
+
+    // Adds a new output with the given amount key and returns its public
+    // key index and global index
+    pub fn add_output(&mut self, amount_key: u64) -> (u32, u64) {
+        let count = self.num_outputs_per_amount.entry(amount_key).or_insert(0);
+        let global_index = *count;
+        *count += 1;
+        let pk_index = self.pk_indices.len() as u32;
+        self.pk_indices.insert((amount_key, global_index), pk_index);
+        (pk_index, global_index)
+    }
+
+    // Adds an output read back from an outputs file
+    pub fn insert(&mut self, amount_key: u64, global_index: u64, pk_index: u32) {
+        let count = self.num_outputs_per_amount.entry(amount_key).or_insert(0);
+        *count = (*count).max(global_index + 1);
+        self.pk_indices.insert((amount_key, global_index), pk_index);
+    }
+
+    pub fn pk_index(&self, amount_key: u64, global_index: u64) -> Option<u32> {
+        self.pk_indices.get(&(amount_key, global_index)).copied()
+    }
+}
@@ -0,0 +1,204 @@
+// Hexadecimal identifiers of key images and public keys.
+//
+// Key images and output public keys are 32 byte values written as 64
+// hexadecimal characters. The interner assigns consecutive indices to them
+// in the order they are first seen, so that edge files with hexadecimal
+// identifiers can be analyzed like the files with pre-densified indices.
+// Persisting the dictionary keeps the indices stable across runs and lets
+// data from different chains be merged by the actual key image or output.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use clap::{Arg, ArgMatches};
+
+use crate::{read_edges, read_lines};
+
+pub type Identifier = [u8; 32];
+
+#[derive(Default)]
+pub struct Interner {
+    indices: HashMap<Identifier, u32>,
+    identifiers: Vec<Identifier>,
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.identifiers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.identifiers.is_empty()
+    }
+
+    // Returns the index of the identifier, assigning the next free index
+    // if it has not been seen before
+    pub fn intern(&mut self, id: &Identifier) -> u32 {
+        if let Some(index) = self.indices.get(id) {
+            return *index;
+        }
+        let index = self.identifiers.len() as u32;
+        self.indices.insert(*id, index);
+        self.identifiers.push(*id);
+        index
+    }
+
+    pub fn get(&self, id: &Identifier) -> Option<u32> {
+        self.indices.get(id).copied()
+    }
+
+    pub fn identifier(&self, index: u32) -> &Identifier {
+        &self.identifiers[index as usize]
+    }
+}
+
+// Parses a string of hexadecimal characters into bytes
+pub fn hex_to_bytes(
+    hex_str: &str,
+) -> Option<Vec<u8>> {
+    let bytes = hex_str.as_bytes();
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let nibble = |c: u8| -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    };
+
+    bytes.chunks(2)
+        .map(|pair| Some((nibble(pair[0])? << 4) | nibble(pair[1])?))
+        .collect()
+}
+
+// Parses 64 hexadecimal characters into an identifier
+pub fn parse_hex_identifier(
+    hex_str: &str,
+) -> Option<Identifier> {
+    hex_to_bytes(hex_str)?.try_into().ok()
+}
+
+pub fn hex_identifier(
+    id: &Identifier,
+) -> String {
+    id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Reads a dictionary written by write_dictionary. The identifier on the
+// ith line gets the index i. A missing file gives an empty dictionary.
+pub fn read_dictionary(
+    filename: &str,
+) -> Interner {
+    let mut interner = Interner::new();
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let id = parse_hex_identifier(line.trim()).expect("Invalid identifier in dictionary");
+            let index = interner.intern(&id);
+            assert_eq!(index as usize + 1, interner.len(), "Duplicate identifier in dictionary");
+        }
+    }
+    interner
+}
+
+// Writes one hexadecimal identifier per line in index order
+pub fn write_dictionary(
+    interner: &Interner,
+    filename: &str,
+) {
+    let file_err_msg = "Unable to create or write to file";
+    let output_file = File::create(filename).expect(file_err_msg);
+    let mut buf = BufWriter::new(output_file);
+
+    for id in &interner.identifiers {
+        writeln!(buf, "{}", hex_identifier(id)).expect(file_err_msg);
+    }
+}
+
+// Reads a text file containing one edge per row, where each row has a
+// hexadecimal key image and a hexadecimal output public key separated by
+// a space. The identifiers are interned into the given dictionaries.
+// The return values are the same as those of read_edges, except that the
+// maximum indices are one less than the dictionary sizes.
+pub fn read_hex_edges(
+    filename: &str,
+    ki_interner: &mut Interner,
+    pk_interner: &mut Interner,
+) -> (Vec<u32>, Vec<u32>, u32, u32) {
+    let mut pk_indices: Vec<u32> = vec![];
+    let mut ki_indices: Vec<u32> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 1);
+
+            let ki = parse_hex_identifier(items[0]).expect("Invalid key image identifier");
+            let pk = parse_hex_identifier(items[1]).expect("Invalid public key identifier");
+            ki_indices.push(ki_interner.intern(&ki));
+            pk_indices.push(pk_interner.intern(&pk));
+        }
+    }
+
+    let max_pk_index = pk_interner.len().saturating_sub(1) as u32;
+    let max_ki_index = ki_interner.len().saturating_sub(1) as u32;
+    (pk_indices, ki_indices, max_pk_index, max_ki_index)
+}
+
+// Reads an edge file with hexadecimal identifiers using the dictionaries
+// persisted in the given files, and saves the updated dictionaries
+pub fn read_hex_edges_with_dictionaries(
+    filename: &str,
+    ki_dict_filename: &str,
+    pk_dict_filename: &str,
+) -> (Vec<u32>, Vec<u32>, u32, u32) {
+    let mut ki_interner = read_dictionary(ki_dict_filename);
+    let mut pk_interner = read_dictionary(pk_dict_filename);
+    let edges = read_hex_edges(filename, &mut ki_interner, &mut pk_interner);
+    write_dictionary(&ki_interner, ki_dict_filename);
+    write_dictionary(&pk_interner, pk_dict_filename);
+    edges
+}
+
+// The --keyimage-dict and --pubkey-dict arguments of the commands that
+// accept edge files with hexadecimal identifiers
+pub fn dictionary_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("keyimage_dict")
+            .long("keyimage-dict")
+            .value_name("Key Image Dictionary Filename")
+            .requires("pubkey_dict")
+            .long_help("The name of the key image dictionary file. When given with --pubkey-dict, the edge files have \
+            hexadecimal key images and public keys instead of indices. The identifiers are converted to indices using \
+            the dictionaries, and new identifiers are appended to them"),
+        Arg::new("pubkey_dict")
+            .long("pubkey-dict")
+            .value_name("Public Key Dictionary Filename")
+            .requires("keyimage_dict")
+            .long_help("The name of the public key dictionary file used with --keyimage-dict"),
+    ]
+}
+
+// Reads an edge file of a command with dictionary_args. The file has
+// hexadecimal identifiers if the dictionaries are given and indices
+// otherwise.
+pub fn read_edges_with_args(
+    filename: &str,
+    m: &ArgMatches,
+) -> (Vec<u32>, Vec<u32>, u32, u32) {
+    match (m.get_one::<String>("keyimage_dict"), m.get_one::<String>("pubkey_dict")) {
+        (Some(ki_dict), Some(pk_dict)) => read_hex_edges_with_dictionaries(filename, ki_dict, pk_dict),
+        _ => read_edges(&filename.to_owned()),
+    }
+}
@@ -4,8 +4,25 @@ use std::io::{self, BufRead, BufWriter, Write};
 
 use sprs::CsMatI;
 
+pub mod amounts;
+pub mod beliefprop;
+pub mod chains;
+pub mod db;
+pub mod dm;
+pub mod entropy;
+pub mod flooding;
+pub mod forks;
+pub mod guess_newest;
+pub mod ingest;
+pub mod interner;
+pub mod matchings;
+pub mod precheck;
+pub mod sideinfo;
+pub mod temporal;
+pub mod txprefix;
+
 // Code from https://doc.rust-lang.org/rust-by-example/std_misc/file/read_lines.html
-fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
+pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<File>>>
 where P: AsRef<Path>, {
     let file = File::open(filename)?;
     Ok(io::BufReader::new(file).lines())
@@ -60,6 +77,86 @@ pub fn write_edges(
     }
 }
 
+// Reads a text file containing one block height per row.
+// Each row has an index (of a key image or a public key) followed by
+// a block height, separated by a space. Any further columns are ignored.
+// Returns a vector indexed by the first column. Indices that do not appear
+// in the file have the height u32::MAX, which denotes an unknown height.
+pub fn read_heights(
+    filename: &str,
+) -> Vec<u32> {
+    read_heights_and_coinbase_flags(filename, false).0
+}
+
+// Reads a text file containing the creation height of each output.
+// The format is the same as the one read by read_heights, except that
+// each row can have an optional third column which is 1 if the output
+// was created in a coinbase transaction and 0 otherwise.
+// Returns the vector of heights and the vector of coinbase flags.
+pub fn read_output_heights(
+    filename: &str,
+) -> (Vec<u32>, Vec<bool>) {
+    read_heights_and_coinbase_flags(filename, true)
+}
+
+fn read_heights_and_coinbase_flags(
+    filename: &str,
+    read_coinbase_flags: bool,
+) -> (Vec<u32>, Vec<bool>) {
+    let mut heights: Vec<u32> = vec![];
+    let mut coinbase: Vec<bool> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 1);
+
+            let index = items[0].parse::<usize>().unwrap();
+            let height = items[1].parse::<u32>().unwrap();
+            if index >= heights.len() {
+                heights.resize(index + 1, u32::MAX);
+                coinbase.resize(index + 1, false);
+            }
+            heights[index] = height;
+            if read_coinbase_flags && items.len() > 2 {
+                coinbase[index] = items[2].parse::<u8>().unwrap() != 0;
+            }
+        }
+    }
+    (heights, coinbase)
+}
+
+// Reads a text file containing the amount of each key image or output.
+// Each row has an index followed by an amount in atomic units, separated
+// by a space. Indices that do not appear in the file have the amount
+// u64::MAX, which denotes an unknown amount. RingCT amounts are 0.
+pub fn read_amounts(
+    filename: &str,
+) -> Vec<u64> {
+    let mut amounts: Vec<u64> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 1);
+
+            let index = items[0].parse::<usize>().unwrap();
+            let amount = items[1].parse::<u64>().unwrap();
+            if index >= amounts.len() {
+                amounts.resize(index + 1, u64::MAX);
+            }
+            amounts[index] = amount;
+        }
+    }
+    amounts
+}
+
 // Read a list of rings from an input file.
 // The first line of the file has the number of rings and number of distinct
 // public keys separated by a single space.
@@ -94,7 +191,7 @@ pub fn read_rings(
             for line in lines {
                 if let Ok(ring) = line {
                     let items: Vec<&str> = ring.trim().split(' ').collect();
-                    assert!(items.len() > 1);
+                    assert!(!items.is_empty()); // Rings can be empty
 
                     let keyimage_index = items[0].parse::<usize>().unwrap();
                     for pk in items[1..].into_iter() {
@@ -168,6 +265,157 @@ pub fn write_ring_sets(
     }
 }
 
+// Read the blocks of the DM fine decomposition written by dmdec.
+// The first line of the file has the number of blocks. Each block is
+// described by three lines. The first line has the size of the block,
+// the second line has the public key indices in the block and the third
+// line has the key image indices in the block.
+// The output vector has one (public keys, key images) pair per block.
+pub fn read_fine_decomposition(
+    filename: &str,
+) -> Vec<(Vec<u32>, Vec<u32>)> {
+    let mut blocks: Vec<(Vec<u32>, Vec<u32>)> = vec![];
+    let parse_indices = |line: Option<String>| -> Vec<u32> {
+        line.unwrap_or_default()
+            .split_whitespace()
+            .map(|x| x.parse::<u32>().unwrap())
+            .collect()
+    };
+
+    if let Ok(lines) = read_lines(filename) {
+        let mut lines = lines.map_while(Result::ok);
+        if let Some(num_blocks_str) = lines.next() {
+            let num_blocks = num_blocks_str.trim().parse::<usize>().unwrap();
+            blocks.reserve(num_blocks);
+
+            for _ in 0..num_blocks {
+                let block_size = lines.next().unwrap().trim().parse::<usize>().unwrap();
+                let pks = parse_indices(lines.next());
+                let kis = parse_indices(lines.next());
+                assert_eq!(pks.len(), block_size);
+                assert_eq!(kis.len(), block_size);
+                blocks.push((pks, kis));
+            }
+        }
+    }
+    blocks
+}
+
+// Reads a text file of spend probabilities written by the marginals command.
+// Each row has a key image index, a public key index and the probability
+// that the key image spends the public key, separated by spaces.
+pub fn read_edge_probabilities(
+    filename: &str,
+) -> Vec<(u32, u32, f64)> {
+    let mut edge_probs: Vec<(u32, u32, f64)> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 2);
+
+            let keyimage_index = items[0].parse::<u32>().unwrap();
+            let pubkey_index = items[1].parse::<u32>().unwrap();
+            let prob = items[2].parse::<f64>().unwrap();
+            edge_probs.push((keyimage_index, pubkey_index, prob));
+        }
+    }
+    edge_probs
+}
+
+// Reads a text file of known spends obtained from side information.
+// Each row has a key image index followed by the index of the public key
+// that it is known to spend, separated by a space.
+pub fn read_known_spends(
+    filename: &str,
+) -> Vec<(u32, u32)> {
+    let mut known_spends: Vec<(u32, u32)> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            let items: Vec<&str> = line.split_whitespace().collect();
+            if items.is_empty() {
+                continue;
+            }
+            assert!(items.len() > 1);
+
+            let keyimage_index = items[0].parse::<u32>().unwrap();
+            let pubkey_index = items[1].parse::<u32>().unwrap();
+            known_spends.push((keyimage_index, pubkey_index));
+        }
+    }
+    known_spends
+}
+
+// Reads a text file containing one index per row, for example the
+// indices of a set of public keys. Only the first column of each row
+// is read.
+pub fn read_indices(
+    filename: &str,
+) -> Vec<u32> {
+    let mut indices: Vec<u32> = vec![];
+
+    if let Ok(lines) = read_lines(filename) {
+        for line in lines.map_while(Result::ok) {
+            if let Some(item) = line.split_whitespace().next() {
+                indices.push(item.parse::<u32>().unwrap());
+            }
+        }
+    }
+    indices
+}
+
+// Performs one iteration of the cascade attack. The public key of every
+// zero-mixin ring that has not been traced yet is removed from all other
+// rings. tx_ring_traced marks the rings whose public key has already been
+// removed. Returns the number of zero-mixin rings after the iteration.
+pub fn cascade_attack_iteration(
+    tx_rings: &mut [Vec<u32>],
+    pk_to_ki_map: &[Vec<u32>],
+    tx_ring_traced: &mut [bool],
+) -> usize {
+    for i in 0..tx_rings.len() {
+        if !tx_ring_traced[i] && tx_rings[i].len() == 1 {
+            let traced_pk = tx_rings[i][0];
+
+            for ki in &pk_to_ki_map[traced_pk as usize] {
+                if *ki != (i as u32) {
+                    let ring = &mut tx_rings[*ki as usize];
+                    if let Some(j) = ring.iter().position(|pk| *pk == traced_pk) {
+                        ring.swap_remove(j); // Assumes that ring keys are unique
+                    }
+                }
+            }
+
+            tx_ring_traced[i] = true;
+        }
+    }
+    tx_rings.iter().filter(|ring| ring.len() == 1).count()
+}
+
+// Runs the cascade attack until the number of zero-mixin rings stops
+// changing or max_iterations iterations have been performed.
+// Returns the number of zero-mixin rings at the end.
+pub fn cascade_attack(
+    tx_rings: &mut [Vec<u32>],
+    pk_to_ki_map: &[Vec<u32>],
+    max_iterations: usize,
+) -> usize {
+    let mut tx_ring_traced = vec![false; tx_rings.len()];
+    let mut num_traceable_rings = tx_rings.iter().filter(|ring| ring.len() == 1).count();
+    for _ in 0..max_iterations {
+        let prev_num_traceable_rings = num_traceable_rings;
+        num_traceable_rings = cascade_attack_iteration(tx_rings, pk_to_ki_map, &mut tx_ring_traced);
+        if prev_num_traceable_rings == num_traceable_rings {
+            break;
+        }
+    }
+    num_traceable_rings
+}
+
 // Function finds a maximum matching in a graph in the case
 // when every column is matched.
 // The output is a vector of u32 values where a value of
@@ -177,13 +425,28 @@ pub fn write_ring_sets(
 pub fn cn_txgraph_maximum_matching(
     g: &CsMatI<u8, u32>,
 ) -> Vec<u32> {
-    let num_rows = g.rows();
+    let mut row_mates = vec![u32::MAX; g.rows()];
+    cn_txgraph_augment_matching(g, &mut row_mates, 0);
+    row_mates
+}
+
+// Function augments an existing matching by searching for augmenting
+// paths starting from the columns first_col, first_col+1, ..., up to the
+// last column. The columns from first_col onwards are assumed to be
+// unmatched in row_mates, which has one entry per row as described for
+// cn_txgraph_maximum_matching. This is used to extend a maximum matching
+// when new columns (key images) are appended to the graph.
+pub fn cn_txgraph_augment_matching(
+    g: &CsMatI<u8, u32>,
+    row_mates: &mut [u32],
+    first_col: usize,
+) {
     let num_cols = g.cols();
     let col_ptr = g.indptr();
     let row_ptr_slice = g.indices();
 
     let col_ptr_slice = col_ptr.as_slice().unwrap();
-    let mut row_mates = vec![u32::MAX; num_rows];
+    assert_eq!(row_mates.len(), g.rows());
 
     let mut dfs_stack = vec![0_u32; num_cols];
     let mut col_mates_in_dfs_stack = vec![0_u32; num_cols];
@@ -196,7 +459,7 @@ pub fn cn_txgraph_maximum_matching(
     let mut head: i64;
     let mut p: i64;
 
-    for current_col in 0..num_cols as u32 {
+    for current_col in first_col as u32..num_cols as u32 {
         dfs_stack[0] = current_col;
         found_unmatched_row = false;
         head = 0_i64;
@@ -248,8 +511,6 @@ pub fn cn_txgraph_maximum_matching(
             }
         }
     }
-
-    return row_mates;
 }
 
 pub fn remove_closed_set_pks(
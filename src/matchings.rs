@@ -0,0 +1,515 @@
+// Perfect matchings within the blocks of the DM fine decomposition.
+//
+// Every block of the fine decomposition is a closed set with as many key
+// images as public keys. Each perfect matching of the block is one possible
+// assignment of real spends to its key images. Assuming that every perfect
+// matching is equally likely, the probability that a key image spends a
+// public key is the fraction of perfect matchings containing that edge.
+// For small blocks this is calculated exactly using the permanent of the
+// block's biadjacency matrix. For large blocks it is estimated using a
+// Markov chain on perfect and near-perfect matchings.
+
+use std::collections::HashMap;
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+// Bipartite graph of a single block with locally numbered vertices
+pub struct BlockGraph {
+    // Global key image index of each local key image
+    pub kis: Vec<u32>,
+    // Global public key index of each local public key
+    pub pks: Vec<u32>,
+    // adj[i] has the local indices of the public keys in the ring of the
+    // ith local key image that belong to the block
+    pub adj: Vec<Vec<usize>>,
+}
+
+impl BlockGraph {
+    // Builds the graph of a block with the given public keys and key images.
+    // Ring members outside the block are ignored.
+    pub fn new(
+        pks: &[u32],
+        kis: &[u32],
+        tx_rings: &[Vec<u32>],
+    ) -> BlockGraph {
+        let local_pk: HashMap<u32, usize> = pks.iter().enumerate().map(|(i, pk)| (*pk, i)).collect();
+        let adj = kis.iter()
+            .map(|ki| {
+                let mut nbrs: Vec<usize> = tx_rings[*ki as usize].iter()
+                    .filter_map(|pk| local_pk.get(pk).copied())
+                    .collect();
+                nbrs.sort_unstable();
+                nbrs.dedup();
+                nbrs
+            })
+            .collect();
+
+        BlockGraph {
+            kis: kis.to_vec(),
+            pks: pks.to_vec(),
+            adj,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.kis.len()
+    }
+
+    fn dense_matrix(&self) -> Vec<Vec<bool>> {
+        let n = self.size();
+        let mut a = vec![vec![false; n]; n];
+        for (i, nbrs) in self.adj.iter().enumerate() {
+            for j in nbrs {
+                a[i][*j] = true;
+            }
+        }
+        a
+    }
+
+    // Finds a perfect matching of the block using augmenting paths.
+    // Returns a vector whose ith entry is the local public key matched to the
+    // ith local key image, or None if the block has no perfect matching.
+    pub fn perfect_matching(&self) -> Option<Vec<usize>> {
+        let n = self.size();
+        let mut ki_mate = vec![usize::MAX; n];
+        let mut pk_mate = vec![usize::MAX; n];
+
+        fn augment(
+            i: usize,
+            adj: &[Vec<usize>],
+            visited: &mut [bool],
+            ki_mate: &mut [usize],
+            pk_mate: &mut [usize],
+        ) -> bool {
+            for j in &adj[i] {
+                if visited[*j] {
+                    continue;
+                }
+                visited[*j] = true;
+                if pk_mate[*j] == usize::MAX || augment(pk_mate[*j], adj, visited, ki_mate, pk_mate) {
+                    ki_mate[i] = *j;
+                    pk_mate[*j] = i;
+                    return true;
+                }
+            }
+            false
+        }
+
+        for i in 0..n {
+            let mut visited = vec![false; n];
+            if !augment(i, &self.adj, &mut visited, &mut ki_mate, &mut pk_mate) {
+                return None;
+            }
+        }
+        Some(ki_mate)
+    }
+}
+
+// Largest matrix size whose permanent is calculated. Ryser's formula adds
+// 2^n products of row sums that are at most n each, so its partial sums are
+// bounded by 2^n n^n, which only fits in an i128 (about 1.7e38) for n <= 22.
+pub const MAX_PERMANENT_SIZE: usize = 22;
+
+// Calculates the permanent of a square 0-1 matrix using Ryser's formula
+// with Gray code ordering of the column subsets. This takes O(2^n n) time.
+// The matrix can have at most MAX_PERMANENT_SIZE rows.
+pub fn permanent(
+    a: &[Vec<bool>],
+) -> i128 {
+    let n = a.len();
+    if n == 0 {
+        return 1;
+    }
+    assert!(n <= MAX_PERMANENT_SIZE, "Permanents of matrices larger than {} overflow", MAX_PERMANENT_SIZE);
+
+    let col_rows: Vec<Vec<usize>> = (0..n)
+        .map(|j| (0..n).filter(|i| a[*i][j]).collect())
+        .collect();
+    let mut row_sums = vec![0_i64; n];
+    let mut total: i128 = 0;
+    let mut gray: u64 = 0;
+
+    for k in 1..(1_u64 << n) {
+        let j = k.trailing_zeros() as usize;
+        gray ^= 1 << j;
+        let delta = if gray & (1 << j) != 0 { 1 } else { -1 };
+        for i in &col_rows[j] {
+            row_sums[*i] += delta;
+        }
+
+        let mut prod: i128 = 1;
+        for r in &row_sums {
+            prod *= *r as i128;
+            if prod == 0 {
+                break;
+            }
+        }
+        if gray.count_ones() % 2 == 1 {
+            total -= prod;
+        }
+        else {
+            total += prod;
+        }
+    }
+
+    if n % 2 == 1 { -total } else { total }
+}
+
+// Calculates the exact probability of every edge of the block under the
+// uniform distribution over its perfect matchings. The output has the same
+// shape as block.adj. Returns None if the block has no perfect matching.
+pub fn exact_edge_probabilities(
+    block: &BlockGraph,
+) -> Option<Vec<Vec<f64>>> {
+    let n = block.size();
+    let a = block.dense_matrix();
+    let num_matchings = permanent(&a);
+    if num_matchings == 0 {
+        return None;
+    }
+
+    let mut probs = vec![];
+    for (i, nbrs) in block.adj.iter().enumerate() {
+        let mut row_probs = vec![];
+        for j in nbrs {
+            let minor: Vec<Vec<bool>> = (0..n).filter(|r| *r != i)
+                .map(|r| (0..n).filter(|c| *c != *j).map(|c| a[r][c]).collect())
+                .collect();
+            row_probs.push(permanent(&minor) as f64 / num_matchings as f64);
+        }
+        probs.push(row_probs);
+    }
+    Some(probs)
+}
+
+// Markov chain on the perfect and near-perfect matchings of a block
+// (Broder's chain as analyzed by Jerrum and Sinclair). The chain is
+// symmetric, so its stationary distribution is uniform over the perfect and
+// near-perfect matchings, and its restriction to perfect matchings is uniform.
+pub struct MatchingChain {
+    ki_mate: Vec<usize>,
+    pk_mate: Vec<usize>,
+    // Unmatched key image and public key when the matching is near-perfect
+    hole: Option<(usize, usize)>,
+    edges: Vec<(usize, usize)>,
+    rng: StdRng,
+}
+
+impl MatchingChain {
+    // Starts the chain from a perfect matching of the block.
+    // Returns None if the block has no perfect matching.
+    pub fn new(block: &BlockGraph, seed: u64) -> Option<MatchingChain> {
+        let ki_mate = block.perfect_matching()?;
+        let mut pk_mate = vec![usize::MAX; block.size()];
+        for (i, j) in ki_mate.iter().enumerate() {
+            pk_mate[*j] = i;
+        }
+        let edges = block.adj.iter().enumerate()
+            .flat_map(|(i, nbrs)| nbrs.iter().map(move |j| (i, *j)))
+            .collect();
+
+        Some(MatchingChain {
+            ki_mate,
+            pk_mate,
+            hole: None,
+            edges,
+            rng: StdRng::seed_from_u64(seed),
+        })
+    }
+
+    pub fn is_perfect(&self) -> bool {
+        self.hole.is_none()
+    }
+
+    // Local public key matched to each local key image. Only meaningful
+    // when the current matching is perfect.
+    pub fn matching(&self) -> &[usize] {
+        &self.ki_mate
+    }
+
+    // Makes one transition of the lazy chain
+    pub fn step(&mut self) {
+        if self.rng.gen_bool(0.5) || self.edges.is_empty() {
+            return;
+        }
+        let (u, v) = self.edges[self.rng.gen_range(0..self.edges.len())];
+
+        match self.hole {
+            None => {
+                // Remove the edge if it is in the matching
+                if self.ki_mate[u] == v {
+                    self.ki_mate[u] = usize::MAX;
+                    self.pk_mate[v] = usize::MAX;
+                    self.hole = Some((u, v));
+                }
+            },
+            Some((hu, hv)) => {
+                if u == hu && v == hv {
+                    // Add the edge to complete the matching
+                    self.ki_mate[u] = v;
+                    self.pk_mate[v] = u;
+                    self.hole = None;
+                }
+                else if u == hu {
+                    // Shift the hole to the key image currently matched to v
+                    let w = self.pk_mate[v];
+                    self.ki_mate[w] = usize::MAX;
+                    self.ki_mate[u] = v;
+                    self.pk_mate[v] = u;
+                    self.hole = Some((w, hv));
+                }
+                else if v == hv {
+                    // Shift the hole to the public key currently matched to u
+                    let z = self.ki_mate[u];
+                    self.pk_mate[z] = usize::MAX;
+                    self.ki_mate[u] = v;
+                    self.pk_mate[v] = u;
+                    self.hole = Some((hu, z));
+                }
+            },
+        }
+    }
+}
+
+// Estimates the probability of every edge of the block under the uniform
+// distribution over its perfect matchings by running the matching chain.
+// The estimate is the fraction of time the chain spends in perfect
+// matchings containing each edge, after discarding burn_in steps.
+// Also returns the number of steps spent in perfect matchings.
+// Returns None if the block has no perfect matching.
+pub fn sampled_edge_probabilities(
+    block: &BlockGraph,
+    num_steps: usize,
+    burn_in: usize,
+    seed: u64,
+) -> Option<(Vec<Vec<f64>>, usize)> {
+    let mut chain = MatchingChain::new(block, seed)?;
+    for _ in 0..burn_in {
+        chain.step();
+    }
+
+    let n = block.size();
+    let mut counts = vec![vec![0_usize; n]; n];
+    let mut num_perfect = 0_usize;
+    for _ in 0..num_steps {
+        chain.step();
+        if chain.is_perfect() {
+            num_perfect += 1;
+            for (i, j) in chain.matching().iter().enumerate() {
+                counts[i][*j] += 1;
+            }
+        }
+    }
+
+    let probs = block.adj.iter().enumerate()
+        .map(|(i, nbrs)| {
+            nbrs.iter()
+                .map(|j| if num_perfect > 0 { counts[i][*j] as f64 / num_perfect as f64 } else { 0.0 })
+                .collect()
+        })
+        .collect();
+    Some((probs, num_perfect))
+}
+
+// Finds a cycle in the directed graph on the key images of the block that
+// has an edge from key image i to key image k when the ring of i contains
+// the public key matched to k. Each such cycle is an alternating cycle of
+// the matching. Returns the cycle as a list of (key image, public key)
+// pairs, where the public key is the one the key image gets when the
+// matching is switched along the cycle.
+fn find_alternating_cycle(
+    adj: &[Vec<usize>],
+    ki_mate: &[usize],
+    pk_mate: &[usize],
+) -> Option<Vec<(usize, usize)>> {
+    let n = adj.len();
+    // 0 = unvisited, 1 = on the DFS stack, 2 = finished
+    let mut state = vec![0_u8; n];
+    let mut next_nbr = vec![0_usize; n];
+    let mut stack: Vec<usize> = vec![];
+
+    for root in 0..n {
+        if state[root] != 0 {
+            continue;
+        }
+        stack.push(root);
+        state[root] = 1;
+
+        while let Some(&i) = stack.last() {
+            if next_nbr[i] == adj[i].len() {
+                state[i] = 2;
+                stack.pop();
+                continue;
+            }
+            let j = adj[i][next_nbr[i]];
+            next_nbr[i] += 1;
+            if j == ki_mate[i] {
+                continue;
+            }
+            let k = pk_mate[j];
+            if state[k] == 0 {
+                state[k] = 1;
+                stack.push(k);
+            }
+            else if state[k] == 1 {
+                // Found a cycle k -> ... -> i -> k on the stack
+                let start = stack.iter().position(|x| *x == k).unwrap();
+                let cycle_kis = &stack[start..];
+                let mut cycle = vec![];
+                for (pos, ki) in cycle_kis.iter().enumerate() {
+                    let next_ki = cycle_kis[(pos + 1) % cycle_kis.len()];
+                    cycle.push((*ki, ki_mate[next_ki]));
+                }
+                return Some(cycle);
+            }
+        }
+    }
+    None
+}
+
+// Recursive step of the enumeration. The perfect matching ki_mate of the
+// graph adj has already been reported.
+fn enumerate_from(
+    adj: Vec<Vec<usize>>,
+    ki_mate: Vec<usize>,
+    limit: usize,
+    matchings: &mut Vec<Vec<usize>>,
+) {
+    if matchings.len() >= limit {
+        return;
+    }
+    let mut pk_mate = vec![usize::MAX; ki_mate.len()];
+    for (i, j) in ki_mate.iter().enumerate() {
+        pk_mate[*j] = i;
+    }
+    let cycle = match find_alternating_cycle(&adj, &ki_mate, &pk_mate) {
+        Some(c) => c,
+        None => return,
+    };
+
+    let mut other_mate = ki_mate.clone();
+    for (i, j) in &cycle {
+        other_mate[*i] = *j;
+    }
+    matchings.push(other_mate.clone());
+
+    // Split on the matching edge e = (i, ki_mate[i]) of the first key image
+    // on the cycle. Matchings containing e are found in the graph where i
+    // keeps only e and the public key of e is removed from all other rings.
+    // Matchings without e are found in the graph without e.
+    let (i, _) = cycle[0];
+    let e_pk = ki_mate[i];
+
+    let mut adj_with_e = adj.clone();
+    for (k, nbrs) in adj_with_e.iter_mut().enumerate() {
+        if k == i {
+            nbrs.retain(|j| *j == e_pk);
+        }
+        else {
+            nbrs.retain(|j| *j != e_pk);
+        }
+    }
+    enumerate_from(adj_with_e, ki_mate, limit, matchings);
+
+    let mut adj_without_e = adj;
+    adj_without_e[i].retain(|j| *j != e_pk);
+    enumerate_from(adj_without_e, other_mate, limit, matchings);
+}
+
+// Enumerates the perfect matchings of a block, stopping after limit
+// matchings have been found. Each matching is a vector whose ith entry is
+// the local public key matched to the ith local key image. This follows
+// Uno's algorithm, which finds a new matching from an alternating cycle
+// and splits the remaining matchings into those with and without an edge
+// of the cycle, so every matching is found exactly once.
+pub fn enumerate_perfect_matchings(
+    block: &BlockGraph,
+    limit: usize,
+) -> Vec<Vec<usize>> {
+    let mut matchings = vec![];
+    if limit == 0 {
+        return matchings;
+    }
+    if let Some(ki_mate) = block.perfect_matching() {
+        matchings.push(ki_mate.clone());
+        enumerate_from(block.adj.clone(), ki_mate, limit, &mut matchings);
+    }
+    matchings
+}
+
+// Samples perfect matchings of a block uniformly at random.
+// Blocks with at most exact_limit key images are sampled exactly by choosing
+// the public key of each key image in turn with probability proportional to
+// the number of perfect matchings that remain after the choice. Larger blocks
+// are sampled by running the matching chain for num_steps steps between
+// samples and then until it reaches a perfect matching. exact_limit can be
+// at most MAX_PERMANENT_SIZE. Returns an empty vector if the block has no perfect matching.
+pub fn sample_perfect_matchings(
+    block: &BlockGraph,
+    num_samples: usize,
+    exact_limit: usize,
+    num_steps: usize,
+    seed: u64,
+) -> Vec<Vec<usize>> {
+    let n = block.size();
+    let mut samples = vec![];
+
+    if n <= exact_limit {
+        let a = block.dense_matrix();
+        if permanent(&a) == 0 {
+            return samples;
+        }
+        let mut rng = StdRng::seed_from_u64(seed);
+        // The weights of row i only depend on the columns chosen for the
+        // rows before it, so they are computed once per prefix of choices
+        let mut prefix_weights: HashMap<Vec<usize>, Vec<i128>> = HashMap::new();
+        for _ in 0..num_samples {
+            let mut ki_mate = vec![usize::MAX; n];
+            let mut cols: Vec<usize> = (0..n).collect();
+            for i in 0..n {
+                let weights = prefix_weights.entry(ki_mate[..i].to_vec()).or_insert_with(|| {
+                    // Rows and columns still to be matched after row i
+                    let rows: Vec<usize> = (i + 1..n).collect();
+                    cols.iter().enumerate().map(|(c_pos, c)| {
+                        if !a[i][*c] {
+                            return 0;
+                        }
+                        let remaining_cols: Vec<usize> = cols.iter().enumerate()
+                            .filter(|(pos, _)| *pos != c_pos)
+                            .map(|(_, c)| *c)
+                            .collect();
+                        let minor: Vec<Vec<bool>> = rows.iter()
+                            .map(|r| remaining_cols.iter().map(|c| a[*r][*c]).collect())
+                            .collect();
+                        permanent(&minor)
+                    }).collect()
+                });
+                let total: i128 = weights.iter().sum();
+                let mut x = rng.gen_range(0..total);
+                let mut chosen = 0;
+                for (pos, w) in weights.iter().enumerate() {
+                    if x < *w {
+                        chosen = pos;
+                        break;
+                    }
+                    x -= *w;
+                }
+                ki_mate[i] = cols[chosen];
+                cols.remove(chosen);
+            }
+            samples.push(ki_mate);
+        }
+    }
+    else if let Some(mut chain) = MatchingChain::new(block, seed) {
+        for _ in 0..num_samples {
+            for _ in 0..num_steps {
+                chain.step();
+            }
+            while !chain.is_perfect() {
+                chain.step();
+            }
+            samples.push(chain.matching().to_vec());
+        }
+    }
+    samples
+}
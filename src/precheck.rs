@@ -0,0 +1,109 @@
+// Defensive check of a proposed ring before it is broadcast.
+//
+// A wallet choosing decoys for a new ring can check whether the new key
+// image would end up in a closed set of the DM decomposition, i.e. whether
+// its ring would be reduced by the closed set attack, and whether adding
+// the ring would shrink the anonymity sets of existing rings. The check
+// appends the ring as a new column of the transaction graph and updates the
+// DM decomposition of the current graph incrementally.
+
+use sprs::{CsMatI, CsVecI};
+
+use crate::dm::{DmDecomposition, DmState, dm_decomposition_incremental, post_dm_ring_size};
+
+pub struct RingPrecheck {
+    // Index of the new key image, which is the number of existing key images
+    pub new_ki: u32,
+    // True if every ring member is already spent by the key images of
+    // existing closed sets. The new key image then has no mate in a maximum
+    // matching and the ring cannot be spent.
+    pub ring_consumed: bool,
+    // Number of ring members of the new key image after the DM decomposition
+    pub effective_ring_size: usize,
+    // Size of the closed set containing the new key image, if it is in one.
+    // A closed set of size one means that the new key image is traceable.
+    pub closed_set_size: Option<usize>,
+    // Existing key images whose rings shrink after the DM decomposition if
+    // the new ring is added. Each entry has the key image index and the
+    // post DM ring sizes before and after adding the new ring.
+    pub shrunk_rings: Vec<(u32, usize, usize)>,
+}
+
+// Returns a copy of the transaction graph with the ring appended as a new
+// column. The ring must only contain existing public keys.
+pub fn append_ring(
+    g: &CsMatI<u8, u32>,
+    ring: &[u32],
+) -> CsMatI<u8, u32> {
+    let mut pks: Vec<u32> = ring.to_vec();
+    pks.sort_unstable();
+    pks.dedup();
+    assert!(pks.iter().all(|pk| (*pk as usize) < g.rows()), "Ring contains an unknown public key");
+    let data = vec![1_u8; pks.len()];
+    let col = CsVecI::new(g.rows(), pks, data);
+    g.clone().append_outer_csvec(col.view())
+}
+
+// Checks the effect of adding a ring to a transaction graph whose DM
+// decomposition is dmd. The graph is a CSC matrix with public keys as rows
+// and key images as columns.
+pub fn precheck_ring(
+    g: &CsMatI<u8, u32>,
+    dmd: &DmDecomposition,
+    ring: &[u32],
+) -> RingPrecheck {
+    let new_ki = g.cols() as u32;
+    let new_g = append_ring(g, ring);
+    let prev_state = DmState::from_decomposition(g, dmd);
+    let new_dmd = dm_decomposition_incremental(&new_g, &prev_state)
+        .expect("Decomposition must be consistent with the graph");
+
+    let old_pk_block_ids = dmd.pk_block_ids();
+    let new_pk_block_ids = new_dmd.pk_block_ids();
+
+    let ring_consumed = new_dmd.ki_mates[new_ki as usize] == u32::MAX;
+    let new_ki_block_id = new_dmd.ki_block_id(new_ki, &new_pk_block_ids);
+    let closed_set_size = if new_ki_block_id == u32::MAX {
+        None
+    }
+    else {
+        Some(new_dmd.fine_blocks[new_ki_block_id as usize].len())
+    };
+    let mut new_ring: Vec<u32> = ring.to_vec();
+    new_ring.sort_unstable();
+    new_ring.dedup();
+    let effective_ring_size = post_dm_ring_size(&new_ring, new_ki_block_id, &new_pk_block_ids);
+
+    // Only the rings containing public keys of the newly formed blocks can
+    // shrink, since the blocks of the previous decomposition are unchanged
+    let g_csr = new_g.to_csr();
+    let mut candidate_kis: Vec<u32> = vec![];
+    for block in &new_dmd.fine_blocks[dmd.fine_blocks.len()..] {
+        for pk in block {
+            if let Some(row) = g_csr.outer_view(*pk as usize) {
+                candidate_kis.extend(row.indices().iter().filter(|ki| **ki != new_ki));
+            }
+        }
+    }
+    candidate_kis.sort_unstable();
+    candidate_kis.dedup();
+
+    let mut shrunk_rings = vec![];
+    for ki in candidate_kis {
+        let col = g.outer_view(ki as usize).unwrap();
+        let ki_ring = col.indices();
+        let before = post_dm_ring_size(ki_ring, dmd.ki_block_id(ki, &old_pk_block_ids), &old_pk_block_ids);
+        let after = post_dm_ring_size(ki_ring, new_dmd.ki_block_id(ki, &new_pk_block_ids), &new_pk_block_ids);
+        if after < before {
+            shrunk_rings.push((ki, before, after));
+        }
+    }
+
+    RingPrecheck {
+        new_ki,
+        ring_consumed,
+        effective_ring_size,
+        closed_set_size,
+        shrunk_rings,
+    }
+}
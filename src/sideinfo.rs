@@ -0,0 +1,192 @@
+// Side information about real spends.
+//
+// Exchanges, leaked wallet data or test wallets reveal the real spend of
+// some key images. Fixing the rings of these key images to their known
+// spends before an attack removes the spent public keys from the other
+// rings, which can trace further key images. The latter are the knock-on
+// effect of the side information.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use clap::{Arg, ArgMatches};
+
+use crate::read_known_spends;
+
+pub struct KnownSpendsSummary {
+    // Known spends applied to the rings
+    pub applied: usize,
+    // Known spends whose public key is not in the ring of the key image
+    pub not_in_ring: usize,
+    // Known spends that contradict another known spend of the same key
+    // image or of the same public key. None of them are applied.
+    pub conflicting: usize,
+}
+
+impl fmt::Display for KnownSpendsSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Known spends applied = {}", self.applied)?;
+        writeln!(f, "Known spends not in the ring of their keyimage = {}", self.not_in_ring)?;
+        writeln!(f, "Conflicting known spends (ignored) = {}", self.conflicting)
+    }
+}
+
+// Known spends applied to the edges before an attack, along with the key
+// images that the attack traces without them
+pub struct SideInformation {
+    pub known_spends: Vec<(u32, u32)>,
+    pub traced_without: Vec<bool>,
+    pub summary: KnownSpendsSummary,
+}
+
+impl SideInformation {
+    // Counts the key images traced only because of the known spends given
+    // the key images traced by the attack with them
+    pub fn knock_on_traced(&self, traced_with: &[bool]) -> usize {
+        count_knock_on_traced(&self.traced_without, traced_with, &self.known_spends)
+    }
+}
+
+// The --known-spends argument of the attack commands
+pub fn known_spends_arg() -> Arg<'static> {
+    Arg::new("known_spends_file")
+        .long("known-spends")
+        .value_name("Known Spends Filename")
+        .long_help("The name of the file containing key images with known real spends. Each row has a key image \
+        index and the index of the public key it spends. The rings of these key images are fixed to the known \
+        public key before the attack and the additional key images traced because of them are reported")
+}
+
+// Applies the known spends given to a command with known_spends_arg to the
+// edges. The attack is first run on the edges without the known spends and
+// returns the key images it traces. Returns None if no known spends file
+// was given.
+pub fn apply_known_spends_from_args<F>(
+    m: &ArgMatches,
+    pk_indices: &mut Vec<u32>,
+    ki_indices: &mut Vec<u32>,
+    trace_without: F,
+) -> Option<SideInformation>
+where
+    F: FnOnce(&[u32], &[u32]) -> Vec<bool>,
+{
+    let known_spends = read_known_spends(m.get_one::<String>("known_spends_file")?);
+    let traced_without = trace_without(pk_indices, ki_indices);
+    let summary = apply_known_spends_to_edges(pk_indices, ki_indices, &known_spends);
+    Some(SideInformation { known_spends, traced_without, summary })
+}
+
+// Removes the known spends that contradict each other and returns the
+// remaining ones as a map from key image to public key
+fn consistent_known_spends(
+    known_spends: &[(u32, u32)],
+) -> (HashMap<u32, u32>, usize) {
+    let mut pks_of_ki: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut kis_of_pk: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (ki, pk) in known_spends {
+        let pks = pks_of_ki.entry(*ki).or_default();
+        if !pks.contains(pk) {
+            pks.push(*pk);
+            kis_of_pk.entry(*pk).or_default().push(*ki);
+        }
+    }
+
+    let mut num_conflicting = 0_usize;
+    let mut spend_of_ki: HashMap<u32, u32> = HashMap::new();
+    for (ki, pks) in &pks_of_ki {
+        if pks.len() == 1 && kis_of_pk[&pks[0]].len() == 1 {
+            spend_of_ki.insert(*ki, pks[0]);
+        }
+        else {
+            num_conflicting += pks.len();
+        }
+    }
+    (spend_of_ki, num_conflicting)
+}
+
+// Fixes the rings of the key images with known spends to the known public
+// key by removing all other edges of these key images. A known spend whose
+// public key is not in the ring of its key image is ignored.
+pub fn apply_known_spends_to_edges(
+    pk_indices: &mut Vec<u32>,
+    ki_indices: &mut Vec<u32>,
+    known_spends: &[(u32, u32)],
+) -> KnownSpendsSummary {
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let (mut spend_of_ki, conflicting) = consistent_known_spends(known_spends);
+    let num_consistent = spend_of_ki.len();
+
+    let mut in_ring: HashMap<u32, bool> = spend_of_ki.keys().map(|ki| (*ki, false)).collect();
+    for (pk, ki) in pk_indices.iter().zip(ki_indices.iter()) {
+        if spend_of_ki.get(ki) == Some(pk) {
+            in_ring.insert(*ki, true);
+        }
+    }
+    spend_of_ki.retain(|ki, _| in_ring[ki]);
+
+    let keep: Vec<bool> = pk_indices.iter().zip(ki_indices.iter())
+        .map(|(pk, ki)| spend_of_ki.get(ki).is_none_or(|known_pk| known_pk == pk))
+        .collect();
+    let mut e = 0_usize;
+    pk_indices.retain(|_| {
+        e += 1;
+        keep[e - 1]
+    });
+    e = 0;
+    ki_indices.retain(|_| {
+        e += 1;
+        keep[e - 1]
+    });
+
+    KnownSpendsSummary {
+        applied: spend_of_ki.len(),
+        not_in_ring: num_consistent - spend_of_ki.len(),
+        conflicting,
+    }
+}
+
+// Fixes the rings of the key images with known spends to the known public
+// key. A known spend whose public key is not in the ring of its key image
+// is ignored.
+pub fn apply_known_spends_to_rings(
+    tx_rings: &mut [Vec<u32>],
+    known_spends: &[(u32, u32)],
+) -> KnownSpendsSummary {
+    let (spend_of_ki, conflicting) = consistent_known_spends(known_spends);
+
+    let mut applied = 0_usize;
+    for (ki, pk) in &spend_of_ki {
+        if let Some(ring) = tx_rings.get_mut(*ki as usize) {
+            if ring.contains(pk) {
+                *ring = vec![*pk];
+                applied += 1;
+            }
+        }
+    }
+
+    KnownSpendsSummary {
+        applied,
+        not_in_ring: spend_of_ki.len() - applied,
+        conflicting,
+    }
+}
+
+// Counts the key images traced only because of the side information.
+// traced_without and traced_with mark the key images traced by an attack
+// without and with the known spends. Key images with a known spend are not
+// counted since they are traced by the side information itself.
+pub fn count_knock_on_traced(
+    traced_without: &[bool],
+    traced_with: &[bool],
+    known_spends: &[(u32, u32)],
+) -> usize {
+    let mut is_known = vec![false; traced_with.len()];
+    for (ki, _pk) in known_spends {
+        if (*ki as usize) < is_known.len() {
+            is_known[*ki as usize] = true;
+        }
+    }
+    (0..traced_with.len())
+        .filter(|ki| traced_with[*ki] && !traced_without[*ki] && !is_known[*ki])
+        .count()
+}
@@ -0,0 +1,261 @@
+// Temporal consistency checks on rings.
+//
+// A ring can only reference outputs that were unlocked when the spending
+// transaction was mined. An output created at height h can be spent in a
+// block at height H only if H >= h + w, where w is the unlock window
+// (10 blocks for regular outputs and 60 blocks for coinbase outputs).
+// Ring members that violate this cannot be the real spend, so they can be
+// removed before running the cascade attack or the DM decomposition. Since
+// the daemon enforces this rule, any violation also points to a bug in the
+// extraction of outputs, key images or heights.
+
+use std::fmt;
+
+use clap::{Arg, ArgMatches};
+
+use crate::{read_heights, read_output_heights};
+use crate::chains::{chain_arg, chain_profile};
+
+// Number of blocks after which a regular output can be spent
+pub const DEFAULT_SPENDABLE_AGE: u32 = 10;
+// Number of blocks after which a coinbase output can be spent
+pub const COINBASE_UNLOCK_WINDOW: u32 = 60;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TemporalAnomalies {
+    // Ring members created in a block after the spending block
+    pub created_after_spend: usize,
+    // Ring members created at or before the spending block but
+    // still locked at the spend height
+    pub locked_at_spend: usize,
+    // Rings in which every member is invalid. These rings are left
+    // unchanged since removing all members would leave them empty.
+    pub rings_without_valid_members: usize,
+    // Edges that could not be checked because the height of the
+    // key image or the public key is unknown
+    pub unknown_height: usize,
+}
+
+impl TemporalAnomalies {
+    pub fn invalid_members(&self) -> usize {
+        self.created_after_spend + self.locked_at_spend
+    }
+}
+
+impl fmt::Display for TemporalAnomalies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Ring members created after the spending block = {}", self.created_after_spend)?;
+        writeln!(f, "Ring members locked at the spend height = {}", self.locked_at_spend)?;
+        writeln!(f, "Rings without any valid member (left unchanged) = {}", self.rings_without_valid_members)?;
+        writeln!(f, "Edges with unknown heights = {}", self.unknown_height)
+    }
+}
+
+// Changes made to the edges by preprocess_edges_by_height
+#[derive(Default)]
+pub struct EdgeHeightSummary {
+    // Height range of the analyzed key images and the number of removed
+    // edges. None if no range was given.
+    pub height_range: Option<(u32, u32, usize)>,
+    // None if no public key heights were given
+    pub anomalies: Option<TemporalAnomalies>,
+}
+
+impl fmt::Display for EdgeHeightSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((from_height, to_height, num_removed)) = self.height_range {
+            writeln!(f, "Removed {} edges of key images outside the height range [{}, {}]", num_removed, from_height, to_height)?;
+        }
+        if let Some(anomalies) = &self.anomalies {
+            write!(f, "{}", anomalies)?;
+        }
+        Ok(())
+    }
+}
+
+// The arguments of the commands that read edge files, restrict them to the
+// key images in a height range and remove the temporally invalid ring
+// members before an attack
+pub fn edge_height_args() -> Vec<Arg<'static>> {
+    vec![
+        Arg::new("pk_heights_file")
+            .long("pk-heights")
+            .value_name("Public Key Creation Heights Filename")
+            .requires("ki_heights_file")
+            .long_help("The name of the file containing the creation height of each public key, with an optional third \
+            column set to 1 for coinbase outputs. When given with --ki-heights, ring members that were created after \
+            the spending block or were still locked at the spend height are removed before the attack"),
+        Arg::new("ki_heights_file")
+            .long("ki-heights")
+            .value_name("Key Image Spend Heights Filename")
+            .long_help("The name of the file containing the block height at which each key image appeared"),
+        Arg::new("from_height")
+            .long("from-height")
+            .value_name("From Height")
+            .value_parser(clap::value_parser!(u32))
+            .requires("ki_heights_file")
+            .long_help("Only key images that appeared at or after this block height are analyzed"),
+        Arg::new("to_height")
+            .long("to-height")
+            .value_name("To Height")
+            .value_parser(clap::value_parser!(u32))
+            .requires("ki_heights_file")
+            .long_help("Only key images that appeared at or before this block height are analyzed"),
+        chain_arg("The chain whose spendable age and coinbase unlock window are used to remove temporally invalid \
+            ring members")
+            .default_value("monero"),
+    ]
+}
+
+// Removes the edges of the key images outside the height range and then the
+// temporally invalid ring members from the edges read by a command with
+// edge_height_args, if the heights files were given
+pub fn preprocess_edges_by_height(
+    m: &ArgMatches,
+    pk_indices: &mut Vec<u32>,
+    ki_indices: &mut Vec<u32>,
+) -> EdgeHeightSummary {
+    let mut summary = EdgeHeightSummary::default();
+    let ki_heights = m.get_one::<String>("ki_heights_file").map(|f| read_heights(f));
+
+    if let Some(ki_heights) = &ki_heights {
+        let from_height = m.get_one::<u32>("from_height").copied();
+        let to_height = m.get_one::<u32>("to_height").copied();
+        if from_height.is_some() || to_height.is_some() {
+            let from_height = from_height.unwrap_or(0);
+            let to_height = to_height.unwrap_or(u32::MAX);
+            let num_removed = filter_edges_by_height(pk_indices, ki_indices, ki_heights, from_height, to_height);
+            summary.height_range = Some((from_height, to_height, num_removed));
+        }
+    }
+
+    if let (Some(pk_heights_fname), Some(ki_heights)) = (m.get_one::<String>("pk_heights_file"), &ki_heights) {
+        let (pk_heights, pk_coinbase) = read_output_heights(pk_heights_fname);
+        let profile = chain_profile(m.get_one::<String>("chain").unwrap());
+        summary.anomalies = Some(prune_temporally_invalid_edges(
+            pk_indices,
+            ki_indices,
+            &pk_heights,
+            &pk_coinbase,
+            ki_heights,
+            profile.spendable_age,
+            profile.coinbase_unlock_window,
+        ));
+    }
+    summary
+}
+
+// Checks whether an output created at pk_height can be spent at ki_height.
+// Returns None if either height is unknown (u32::MAX).
+fn member_is_spendable(
+    pk_height: u32,
+    pk_is_coinbase: bool,
+    ki_height: u32,
+    spendable_age: u32,
+    coinbase_unlock_window: u32,
+) -> Option<bool> {
+    if pk_height == u32::MAX || ki_height == u32::MAX {
+        return None;
+    }
+    let window = if pk_is_coinbase { coinbase_unlock_window } else { spendable_age };
+    Some((ki_height as u64) >= (pk_height as u64) + (window as u64))
+}
+
+// Removes the edges whose public key could not have been spent by the key
+// image at the height of the key image. The edges are given as vectors of
+// public key and key image indices as returned by read_edges, and are
+// filtered in place. pk_coinbase can be shorter than pk_heights, in which
+// case the missing public keys are treated as regular outputs.
+pub fn prune_temporally_invalid_edges(
+    pk_indices: &mut Vec<u32>,
+    ki_indices: &mut Vec<u32>,
+    pk_heights: &[u32],
+    pk_coinbase: &[bool],
+    ki_heights: &[u32],
+    spendable_age: u32,
+    coinbase_unlock_window: u32,
+) -> TemporalAnomalies {
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let mut anomalies = TemporalAnomalies::default();
+
+    let num_kis = ki_indices.iter().max().map_or(0, |m| *m as usize + 1);
+    let mut num_valid_members = vec![0_u32; num_kis];
+    let mut edge_is_valid = vec![true; pk_indices.len()];
+
+    for e in 0..pk_indices.len() {
+        let pk = pk_indices[e] as usize;
+        let ki = ki_indices[e] as usize;
+        let pk_height = pk_heights.get(pk).copied().unwrap_or(u32::MAX);
+        let ki_height = ki_heights.get(ki).copied().unwrap_or(u32::MAX);
+        let is_coinbase = pk_coinbase.get(pk).copied().unwrap_or(false);
+
+        match member_is_spendable(pk_height, is_coinbase, ki_height, spendable_age, coinbase_unlock_window) {
+            Some(true) => num_valid_members[ki] += 1,
+            Some(false) => {
+                edge_is_valid[e] = false;
+                if pk_height > ki_height {
+                    anomalies.created_after_spend += 1;
+                }
+                else {
+                    anomalies.locked_at_spend += 1;
+                }
+            },
+            None => {
+                anomalies.unknown_height += 1;
+                num_valid_members[ki] += 1;
+            },
+        }
+    }
+
+    let mut ring_counted = vec![false; num_kis];
+    let mut e = 0_usize;
+    pk_indices.retain(|_| {
+        let ki = ki_indices[e] as usize;
+        let keep = edge_is_valid[e] || num_valid_members[ki] == 0;
+        if !edge_is_valid[e] && num_valid_members[ki] == 0 && !ring_counted[ki] {
+            ring_counted[ki] = true;
+            anomalies.rings_without_valid_members += 1;
+        }
+        e += 1;
+        keep
+    });
+    e = 0;
+    ki_indices.retain(|ki| {
+        let keep = edge_is_valid[e] || num_valid_members[*ki as usize] == 0;
+        e += 1;
+        keep
+    });
+    assert_eq!(pk_indices.len(), ki_indices.len());
+
+    anomalies
+}
+
+// Removes the edges of key images whose height lies outside the inclusive
+// range [from_height, to_height]. Key images with unknown heights are also
+// removed. The key image indices are not changed, so the key images outside
+// the range end up with empty rings. Returns the number of removed edges.
+pub fn filter_edges_by_height(
+    pk_indices: &mut Vec<u32>,
+    ki_indices: &mut Vec<u32>,
+    ki_heights: &[u32],
+    from_height: u32,
+    to_height: u32,
+) -> usize {
+    assert_eq!(pk_indices.len(), ki_indices.len());
+    let in_range = |ki: u32| {
+        let h = ki_heights.get(ki as usize).copied().unwrap_or(u32::MAX);
+        h != u32::MAX && h >= from_height && h <= to_height
+    };
+
+    let num_edges = ki_indices.len();
+    let mut e = 0_usize;
+    pk_indices.retain(|_| {
+        let keep = in_range(ki_indices[e]);
+        e += 1;
+        keep
+    });
+    ki_indices.retain(|ki| in_range(*ki));
+    assert_eq!(pk_indices.len(), ki_indices.len());
+
+    num_edges - ki_indices.len()
+}
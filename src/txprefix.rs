@@ -0,0 +1,278 @@
+// Parser of serialized Monero transaction prefixes.
+//
+// A transaction blob begins with its prefix, which has the version, the
+// unlock time, the inputs, the outputs and the extra field. The ring
+// signatures or RingCT data that follow the prefix are not parsed.
+// Integers are varints: 7 bits per byte, least significant group first,
+// with the high bit set on every byte except the last.
+
+use crate::ingest::{keyoffsets_to_keyindices, RingInput};
+use crate::interner::Identifier;
+
+// Input and output type tags
+const TXIN_GEN: u8 = 0xff;
+const TXIN_TO_KEY: u8 = 0x02;
+const TXOUT_TO_KEY: u8 = 0x02;
+const TXOUT_TO_TAGGED_KEY: u8 = 0x03;
+
+pub enum TxInput {
+    // Coinbase input with the height of the block
+    Gen { height: u64 },
+    ToKey { amount: u64, key_offsets: Vec<u64>, key_image: Identifier },
+}
+
+pub struct TxOutput {
+    pub amount: u64,
+    pub key: Identifier,
+    // Outputs since the view tag hard fork have a one byte view tag
+    pub view_tag: Option<u8>,
+}
+
+pub struct TxPrefix {
+    pub version: u64,
+    pub unlock_time: u64,
+    pub inputs: Vec<TxInput>,
+    pub outputs: Vec<TxOutput>,
+    pub extra: Vec<u8>,
+}
+
+impl TxPrefix {
+    // Key inputs with their rings as global output indices
+    pub fn ring_inputs(&self) -> Vec<RingInput> {
+        self.inputs.iter()
+            .filter_map(|txin| match txin {
+                TxInput::Gen { .. } => None,
+                TxInput::ToKey { amount, key_offsets, key_image } => {
+                    let mut key_indices = keyoffsets_to_keyindices(key_offsets);
+                    key_indices.sort_unstable();
+                    key_indices.dedup();
+                    Some(RingInput {
+                        key_image: *key_image,
+                        amount: *amount,
+                        key_indices,
+                    })
+                },
+            })
+            .collect()
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.inputs.first(), Some(TxInput::Gen { .. }))
+    }
+}
+
+// Reads a varint at pos and advances pos past it
+pub fn read_varint(
+    blob: &[u8],
+    pos: &mut usize,
+) -> Option<u64> {
+    let mut value = 0_u64;
+    let mut shift = 0_u32;
+    loop {
+        let byte = *blob.get(*pos)?;
+        *pos += 1;
+        let group = (byte & 0x7f) as u64;
+        if shift >= 64 || (shift > 0 && group >> (64 - shift) != 0) {
+            return None; // Overflow
+        }
+        value |= group << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+fn read_bytes<'a>(
+    blob: &'a [u8],
+    pos: &mut usize,
+    len: usize,
+) -> Option<&'a [u8]> {
+    let bytes = blob.get(*pos..pos.checked_add(len)?)?;
+    *pos += len;
+    Some(bytes)
+}
+
+fn read_identifier(
+    blob: &[u8],
+    pos: &mut usize,
+) -> Option<Identifier> {
+    read_bytes(blob, pos, 32)?.try_into().ok()
+}
+
+// Parses the prefix at the start of a transaction blob. Returns the prefix
+// and its length in bytes, or None if the blob is truncated or has an
+// unsupported input or output type.
+pub fn parse_tx_prefix(
+    blob: &[u8],
+) -> Option<(TxPrefix, usize)> {
+    let mut pos = 0_usize;
+    let version = read_varint(blob, &mut pos)?;
+    let unlock_time = read_varint(blob, &mut pos)?;
+
+    let num_inputs = read_varint(blob, &mut pos)?;
+    let mut inputs: Vec<TxInput> = vec![];
+    for _ in 0..num_inputs {
+        let tag = *read_bytes(blob, &mut pos, 1)?.first()?;
+        let txin = match tag {
+            TXIN_GEN => TxInput::Gen { height: read_varint(blob, &mut pos)? },
+            TXIN_TO_KEY => {
+                let amount = read_varint(blob, &mut pos)?;
+                let num_offsets = read_varint(blob, &mut pos)?;
+                let mut key_offsets: Vec<u64> = vec![];
+                for _ in 0..num_offsets {
+                    key_offsets.push(read_varint(blob, &mut pos)?);
+                }
+                let key_image = read_identifier(blob, &mut pos)?;
+                TxInput::ToKey { amount, key_offsets, key_image }
+            },
+            _ => return None,
+        };
+        inputs.push(txin);
+    }
+
+    let num_outputs = read_varint(blob, &mut pos)?;
+    let mut outputs: Vec<TxOutput> = vec![];
+    for _ in 0..num_outputs {
+        let amount = read_varint(blob, &mut pos)?;
+        let tag = *read_bytes(blob, &mut pos, 1)?.first()?;
+        let key = match tag {
+            TXOUT_TO_KEY | TXOUT_TO_TAGGED_KEY => read_identifier(blob, &mut pos)?,
+            _ => return None,
+        };
+        let view_tag = if tag == TXOUT_TO_TAGGED_KEY {
+            Some(*read_bytes(blob, &mut pos, 1)?.first()?)
+        }
+        else {
+            None
+        };
+        outputs.push(TxOutput { amount, key, view_tag });
+    }
+
+    let extra_len = read_varint(blob, &mut pos)? as usize;
+    let extra = read_bytes(blob, &mut pos, extra_len)?.to_vec();
+
+    let prefix = TxPrefix {
+        version,
+        unlock_time,
+        inputs,
+        outputs,
+        extra,
+    };
+    Some((prefix, pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_varint(blob: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            blob.push((value & 0x7f) as u8 | 0x80);
+            value >>= 7;
+        }
+        blob.push(value as u8);
+    }
+
+    // A coinbase input, a key input, a tagged output, an untagged output and
+    // a three byte extra, followed by bytes that are not part of the prefix
+    fn prefix_blob() -> Vec<u8> {
+        let mut blob = vec![];
+        write_varint(&mut blob, 2);
+        write_varint(&mut blob, 300);
+        write_varint(&mut blob, 2);
+        blob.push(TXIN_GEN);
+        write_varint(&mut blob, 1000);
+        blob.push(TXIN_TO_KEY);
+        write_varint(&mut blob, 0);
+        write_varint(&mut blob, 3);
+        for offset in [5, 130, 1] {
+            write_varint(&mut blob, offset);
+        }
+        blob.extend([7_u8; 32]);
+        write_varint(&mut blob, 2);
+        write_varint(&mut blob, 0);
+        blob.push(TXOUT_TO_TAGGED_KEY);
+        blob.extend([9_u8; 32]);
+        blob.push(0x5a);
+        write_varint(&mut blob, 1234);
+        blob.push(TXOUT_TO_KEY);
+        blob.extend([10_u8; 32]);
+        write_varint(&mut blob, 3);
+        blob.extend([0x01, 0xaa, 0xbb]);
+        blob
+    }
+
+    #[test]
+    fn parses_hand_built_prefix() {
+        let mut blob = prefix_blob();
+        let prefix_len = blob.len();
+        blob.extend([0xde, 0xad]);
+
+        let (prefix, len) = parse_tx_prefix(&blob).unwrap();
+        assert_eq!(len, prefix_len);
+        assert_eq!(prefix.version, 2);
+        assert_eq!(prefix.unlock_time, 300);
+        assert!(prefix.is_coinbase());
+        assert_eq!(prefix.inputs.len(), 2);
+        assert!(matches!(prefix.inputs[0], TxInput::Gen { height: 1000 }));
+        match &prefix.inputs[1] {
+            TxInput::ToKey { amount, key_offsets, key_image } => {
+                assert_eq!(*amount, 0);
+                assert_eq!(key_offsets, &vec![5, 130, 1]);
+                assert_eq!(key_image, &[7_u8; 32]);
+            },
+            TxInput::Gen { .. } => panic!("Expected a key input"),
+        }
+
+        let ring_inputs = prefix.ring_inputs();
+        assert_eq!(ring_inputs.len(), 1);
+        assert_eq!(ring_inputs[0].key_indices, vec![5, 135, 136]);
+        assert_eq!(ring_inputs[0].key_image, [7_u8; 32]);
+
+        assert_eq!(prefix.outputs.len(), 2);
+        assert_eq!(prefix.outputs[0].amount, 0);
+        assert_eq!(prefix.outputs[0].key, [9_u8; 32]);
+        assert_eq!(prefix.outputs[0].view_tag, Some(0x5a));
+        assert_eq!(prefix.outputs[1].amount, 1234);
+        assert_eq!(prefix.outputs[1].key, [10_u8; 32]);
+        assert_eq!(prefix.outputs[1].view_tag, None);
+        assert_eq!(prefix.extra, vec![0x01, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn rejects_truncated_extra() {
+        let blob = prefix_blob();
+        assert!(parse_tx_prefix(&blob[..blob.len() - 1]).is_none());
+    }
+
+    #[test]
+    fn reads_varints() {
+        let mut blob = vec![];
+        for value in [0, 127, 128, 300, u64::MAX] {
+            write_varint(&mut blob, value);
+        }
+        let mut pos = 0;
+        for value in [0, 127, 128, 300, u64::MAX] {
+            assert_eq!(read_varint(&blob, &mut pos), Some(value));
+        }
+        assert_eq!(pos, blob.len());
+        assert_eq!(read_varint(&blob, &mut pos), None);
+    }
+
+    #[test]
+    fn rejects_varint_overflow() {
+        // Bits above the 64th in the last group
+        let mut blob = vec![0xff; 9];
+        blob.push(0x02);
+        assert_eq!(read_varint(&blob, &mut 0), None);
+
+        // More groups than fit in 64 bits
+        let mut blob = vec![0x80; 10];
+        blob.push(0x00);
+        assert_eq!(read_varint(&blob, &mut 0), None);
+
+        // A truncated varint
+        assert_eq!(read_varint(&[0x80, 0x80], &mut 0), None);
+    }
+}